@@ -44,7 +44,7 @@ fn main() {
         unsafe { iv.CloseApp() }
     });
 
-    inkview::iv_main(&iv, move |event| {
+    inkview::iv_main(iv, move |event| {
         if let Err(e) = event_tx.send(event) {
             eprintln!("Sending inkview event failed, Err: {e:?}");
         }
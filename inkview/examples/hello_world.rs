@@ -6,7 +6,7 @@ fn main() {
     let iv = Box::leak(Box::new(inkview::load())) as &_;
     const FONT_SIZE: c_int = 42;
 
-    inkview::iv_main(&iv, move |event| {
+    inkview::iv_main(iv, move |event| {
         match event {
             Event::Init => unsafe {
                 iv.SetCurrentApplicationAttribute(APPLICATION_ATTRIBUTE_APPLICATION_READER, 1);
@@ -39,7 +39,7 @@ fn main() {
         iv.CloseFont(font);
     };
 
-    inkview::iv_main(&iv, move |event| {
+    inkview::iv_main(iv, move |event| {
         match event {
             Event::Init => {
                 unsafe {
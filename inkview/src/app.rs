@@ -0,0 +1,381 @@
+//! Application lifecycle helpers.
+
+use crate::error::{StateError, TaskError};
+use crate::task::{RequestListener, Task};
+use crate::{bindings, Event};
+use std::ffi::CStr;
+use std::path::PathBuf;
+
+type Hook<S> = Option<Box<dyn FnMut(&mut S) + Send>>;
+
+/// Saves and restores the app state, only available with the `serde` feature.
+#[cfg(feature = "serde")]
+struct Persistence<S> {
+    path: PathBuf,
+    save: fn(&S, &std::path::Path) -> std::io::Result<()>,
+    load: fn(&std::path::Path) -> std::io::Result<S>,
+}
+
+/// Tracks the application lifecycle state machine and invokes hooks on transitions.
+///
+/// Feed every event received in the [`crate::iv_main`] handler into [`Lifecycle::handle`].
+///
+/// With the `serde` feature, [`Lifecycle::persistent`] additionally saves the app state `S`
+/// when the app is sent to the background, is asked to save its state, or exits, and restores
+/// it on init. PocketBook may kill background tasks at any time, so the state should not be
+/// expected to survive otherwise.
+pub struct Lifecycle<S = ()> {
+    initialized: bool,
+    visible: bool,
+    foreground: bool,
+    state: S,
+    #[cfg(feature = "serde")]
+    persistence: Option<Persistence<S>>,
+
+    on_init: Hook<S>,
+    on_show: Hook<S>,
+    on_hide: Hook<S>,
+    on_foreground: Hook<S>,
+    on_background: Hook<S>,
+    on_save_state: Hook<S>,
+    on_exit: Hook<S>,
+}
+
+impl Default for Lifecycle<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lifecycle<()> {
+    pub fn new() -> Self {
+        Self::with_state(())
+    }
+}
+
+impl<S> Lifecycle<S> {
+    /// Create a lifecycle holding the app state `state`, which is passed to the hooks.
+    pub fn with_state(state: S) -> Self {
+        Self {
+            initialized: false,
+            visible: false,
+            // Apps are started in the foreground.
+            foreground: true,
+            state,
+            #[cfg(feature = "serde")]
+            persistence: None,
+            on_init: None,
+            on_show: None,
+            on_hide: None,
+            on_foreground: None,
+            on_background: None,
+            on_save_state: None,
+            on_exit: None,
+        }
+    }
+
+    /// Called on [`Event::Init`], after the state was restored.
+    pub fn on_init<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_init = Some(Box::new(f));
+        self
+    }
+
+    /// Called on [`Event::Show`].
+    pub fn on_show<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_show = Some(Box::new(f));
+        self
+    }
+
+    /// Called on [`Event::Hide`].
+    pub fn on_hide<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_hide = Some(Box::new(f));
+        self
+    }
+
+    /// Called on [`Event::Foreground`].
+    pub fn on_foreground<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_foreground = Some(Box::new(f));
+        self
+    }
+
+    /// Called on [`Event::Background`], before the state is saved.
+    pub fn on_background<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_background = Some(Box::new(f));
+        self
+    }
+
+    /// Called on [`Event::SaveState`], before the state is saved.
+    pub fn on_save_state<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_save_state = Some(Box::new(f));
+        self
+    }
+
+    /// Called on [`Event::Exit`], before the state is saved.
+    pub fn on_exit<F: FnMut(&mut S) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_exit = Some(Box::new(f));
+        self
+    }
+
+    /// Update the lifecycle state from `event` and invoke the matching hook.
+    ///
+    /// Fails if restoring or saving the persisted state failed, the hook is invoked regardless.
+    pub fn handle(&mut self, event: &Event) -> Result<(), StateError> {
+        let mut result = Ok(());
+        let hook = match event {
+            Event::Init => {
+                self.initialized = true;
+                result = self.restore();
+                &mut self.on_init
+            }
+            Event::Show => {
+                self.visible = true;
+                &mut self.on_show
+            }
+            Event::Hide => {
+                self.visible = false;
+                &mut self.on_hide
+            }
+            Event::Foreground { .. } => {
+                self.foreground = true;
+                &mut self.on_foreground
+            }
+            Event::Background { .. } => {
+                self.foreground = false;
+                &mut self.on_background
+            }
+            Event::SaveState => &mut self.on_save_state,
+            Event::Exit => &mut self.on_exit,
+            _ => return Ok(()),
+        };
+        if let Some(hook) = hook {
+            hook(&mut self.state);
+        }
+
+        if matches!(
+            event,
+            Event::Background { .. } | Event::SaveState | Event::Exit
+        ) {
+            result = self.save();
+        }
+        result
+    }
+
+    /// Whether [`Event::Init`] was received.
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Whether the app is currently shown.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Whether the app is currently the foreground task.
+    pub fn is_foreground(&self) -> bool {
+        self.foreground
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Restore the persisted state, a missing state file is not an error.
+    #[cfg(feature = "serde")]
+    fn restore(&mut self) -> Result<(), StateError> {
+        let Some(persistence) = &self.persistence else {
+            return Ok(());
+        };
+        match (persistence.load)(&persistence.path) {
+            Ok(state) => self.state = state,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(StateError::Restore {
+                    path: persistence.path.display().to_string(),
+                    message: e.to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn restore(&mut self) -> Result<(), StateError> {
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&mut self) -> Result<(), StateError> {
+        let Some(persistence) = &self.persistence else {
+            return Ok(());
+        };
+        (persistence.save)(&self.state, &persistence.path).map_err(|e| StateError::Save {
+            path: persistence.path.display().to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn save(&mut self) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize + serde::de::DeserializeOwned> Lifecycle<S> {
+    /// Create a lifecycle which persists the app state in `state.json` inside [`config_dir`].
+    ///
+    /// `state` is used until a saved state is restored on [`Event::Init`].
+    pub fn persistent(state: S) -> Self {
+        Self::persistent_at(config_dir().join("state.json"), state)
+    }
+
+    /// Create a lifecycle which persists the app state as JSON at `path`.
+    pub fn persistent_at(path: impl Into<PathBuf>, state: S) -> Self {
+        let mut lifecycle = Self::with_state(state);
+        lifecycle.persistence = Some(Persistence {
+            path: path.into(),
+            save: |state, path| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                // Write to a temporary file first, so a kill while saving
+                // does not leave behind a truncated state.
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, serde_json::to_vec(state)?)?;
+                std::fs::rename(tmp, path)
+            },
+            load: |path| Ok(serde_json::from_slice(&std::fs::read(path)?)?),
+        });
+        lifecycle
+    }
+}
+
+/// Name of the running app, derived from the executable name without the `.app` extension.
+pub fn app_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "inkview-rs".to_string())
+}
+
+/// The configuration directory of the running app, inside the system config path.
+pub fn config_dir() -> PathBuf {
+    let config_path = CStr::from_bytes_with_nul(bindings::CONFIGPATH)
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    PathBuf::from(config_path).join(app_name())
+}
+
+/// Request code used by [`single_instance`] to forward the launch arguments.
+pub const RELAUNCH_REQUEST: i32 = 0x4956_0001;
+
+/// Result of [`single_instance`].
+#[must_use]
+pub enum Instance {
+    /// This is the only instance. Relaunches are forwarded to it while this is kept alive.
+    Primary(RequestListener),
+    /// Another instance was brought to the foreground and got the launch arguments, this one
+    /// should exit.
+    Forwarded,
+}
+
+impl Instance {
+    pub fn is_primary(&self) -> bool {
+        matches!(self, Self::Primary(_))
+    }
+}
+
+/// Make sure only one instance of the app runs.
+///
+/// If the app is already running in another task, it is brought to the foreground and its
+/// `on_relaunch` is called with the launch arguments of this instance, without the executable.
+/// Otherwise this instance starts listening for relaunches.
+///
+/// Call it when handling [`Event::Init`], and close the app on [`Instance::Forwarded`].
+pub fn single_instance<F>(
+    iv: &'static bindings::Inkview,
+    mut on_relaunch: F,
+) -> Result<Instance, TaskError>
+where
+    F: FnMut(Vec<String>) + Send + 'static,
+{
+    // FindTaskByAppName may return this instance, even if another one is running.
+    let current = Task::current(iv);
+    let app_name = app_name();
+    let other = Task::list(iv)
+        .into_iter()
+        .find(|task| *task != current && task.info().is_some_and(|info| info.app_name == app_name));
+    if let Some(task) = other {
+        task.notify(RELAUNCH_REQUEST, &encode_args(std::env::args().skip(1)))?;
+        task.activate()?;
+        return Ok(Instance::Forwarded);
+    }
+
+    let listener = RequestListener::new(iv, RELAUNCH_REQUEST, move |payload| {
+        on_relaunch(decode_args(payload));
+        Vec::new()
+    })?;
+    Ok(Instance::Primary(listener))
+}
+
+/// Join the arguments forwarded by [`single_instance`]. Arguments can't contain nul bytes, so
+/// each one is terminated by a nul byte.
+fn encode_args(args: impl IntoIterator<Item = String>) -> Vec<u8> {
+    args.into_iter()
+        .flat_map(|arg| arg.into_bytes().into_iter().chain([0]))
+        .collect()
+}
+
+/// Split the arguments joined by [`encode_args`], replacing invalid UTF-8.
+fn decode_args(payload: &[u8]) -> Vec<String> {
+    let mut args: Vec<String> = payload
+        .split(|b| *b == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    // Every argument is terminated, which leaves an empty piece at the end.
+    if args.last().is_some_and(|arg| arg.is_empty()) {
+        args.pop();
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn relaunch_args_are_nul_terminated() {
+        assert_eq!(encode_args(args(&[])), b"");
+        assert_eq!(encode_args(args(&["a"])), b"a\0");
+        assert_eq!(encode_args(args(&["", "book.epub"])), b"\0book.epub\0");
+    }
+
+    #[test]
+    fn relaunch_args_round_trip() {
+        for case in [
+            &[][..],
+            &["book.epub"],
+            &[""],
+            &["", ""],
+            &["--open", "/mnt/ext1/Books/Ä ö.epub", ""],
+        ] {
+            assert_eq!(decode_args(&encode_args(args(case))), args(case));
+        }
+    }
+
+    #[test]
+    fn relaunch_args_decode_leniently() {
+        // A missing terminator keeps the last argument.
+        assert_eq!(decode_args(b"a\0b"), args(&["a", "b"]));
+        assert_eq!(decode_args(b"a\xff\0"), args(&["a\u{fffd}"]));
+    }
+}
@@ -0,0 +1,357 @@
+//! Bluetooth power, devices and file transfer, and a custom BLE GATT service.
+//!
+//! The GATT service and BLE scanning require SDK 6.5 or newer, and a firmware providing them.
+//!
+//! `ReadCustomGattService` takes a buffer without its length, see [`GattConnection::read_into`]
+//! for the assumed contract.
+
+use crate::bindings;
+use crate::device::Capabilities;
+use crate::encoding::{c_string, string_from_ptr};
+use crate::error::BluetoothError;
+#[cfg(not(feature = "sdk-5-19"))]
+use crate::timer::Timer;
+use std::ffi::{c_char, c_int};
+use std::path::Path;
+use std::ptr;
+#[cfg(not(feature = "sdk-5-19"))]
+use std::time::{Duration, Instant};
+
+/// Whether the device has Bluetooth.
+pub fn is_available(iv: &bindings::Inkview) -> bool {
+    Capabilities::read(iv).contains(Capabilities::BLUETOOTH)
+}
+
+/// Map a status code of the Bluetooth functions, negative codes are errors.
+fn check(code: c_int) -> Result<c_int, BluetoothError> {
+    if code < 0 {
+        Err(BluetoothError::Failed(code))
+    } else {
+        Ok(code)
+    }
+}
+
+/// The state of the Bluetooth adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Off,
+    Suspended,
+    Ready,
+    Connected,
+    Transferring,
+    Error,
+    Other(u32),
+}
+
+pub fn state(iv: &bindings::Inkview) -> State {
+    match unsafe { iv.GetBluetoothStatus() } {
+        bindings::bt_state_e_BT_STATE_OFF => State::Off,
+        bindings::bt_state_e_BT_STATE_SUSPENDED => State::Suspended,
+        bindings::bt_state_e_BT_STATE_READY => State::Ready,
+        bindings::bt_state_e_BT_STATE_CONNECTED => State::Connected,
+        bindings::bt_state_e_BT_STATE_TRANSFERRING => State::Transferring,
+        bindings::bt_state_e_BT_STATE_ERROR => State::Error,
+        raw => State::Other(raw),
+    }
+}
+
+pub fn is_enabled(iv: &bindings::Inkview) -> bool {
+    unsafe { iv.IsBluetoothEnabled() != 0 }
+}
+
+/// Turn Bluetooth on or off.
+pub fn set_enabled(iv: &bindings::Inkview, enabled: bool) -> Result<(), BluetoothError> {
+    let code = unsafe {
+        if enabled {
+            iv.SetBluetoothOn()
+        } else {
+            iv.SetBluetoothOff()
+        }
+    };
+    check(code).map(|_| ())
+}
+
+/// The known Bluetooth devices, as listed by `EnumBTdevices`.
+pub fn devices(iv: &bindings::Inkview) -> Vec<String> {
+    let mut devices = Vec::new();
+    let mut entry = unsafe { iv.EnumBTdevices() };
+    if entry.is_null() {
+        return devices;
+    }
+    while let Some(device) = unsafe { string_from_ptr(*entry) } {
+        devices.push(device);
+        entry = unsafe { entry.add(1) };
+    }
+    devices
+}
+
+/// Send files over OBEX to the device with the MAC address `mac`.
+pub fn send_files(
+    iv: &bindings::Inkview,
+    mac: &str,
+    files: &[impl AsRef<Path>],
+) -> Result<(), BluetoothError> {
+    let mac = c_string(mac)?;
+    let files = files
+        .iter()
+        .map(|f| c_string(f.as_ref().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut file_ptrs: Vec<*mut c_char> = files.iter().map(|f| f.as_ptr() as *mut _).collect();
+    file_ptrs.push(ptr::null_mut());
+    // Inkview takes mutable pointers, but does not modify the strings.
+    check(unsafe { iv.BtSendFiles(mac.as_ptr() as *mut _, file_ptrs.as_mut_ptr()) }).map(|_| ())
+}
+
+/// A device found by a BLE scan.
+#[cfg(not(feature = "sdk-5-19"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeDevice {
+    pub mac: String,
+    pub name: String,
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+type ScanCallback = Box<dyn FnMut(LeDevice) + Send>;
+
+/// A running BLE scan, stopped on drop.
+#[cfg(not(feature = "sdk-5-19"))]
+#[must_use = "the scan is stopped when dropped"]
+pub struct LeScan {
+    iv: &'static bindings::Inkview,
+    // Double boxed, so the callback data is a thin pointer.
+    callback: *mut ScanCallback,
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+impl LeScan {
+    /// Scan for BLE devices, calling `on_device` for every device found.
+    pub fn start<F: FnMut(LeDevice) + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        on_device: F,
+    ) -> Result<Self, BluetoothError> {
+        if iv.StartScanBTLE.is_err() || iv.StopScanBTLE.is_err() {
+            return Err(BluetoothError::Unavailable);
+        }
+        let callback: *mut ScanCallback = Box::into_raw(Box::new(Box::new(on_device)));
+        let code = unsafe { iv.StartScanBTLE(Some(scan_handler), callback as *mut _) };
+        if let Err(e) = check(code) {
+            drop(unsafe { Box::from_raw(callback) });
+            return Err(e);
+        }
+        Ok(Self { iv, callback })
+    }
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+unsafe extern "C" fn scan_handler(
+    cb_data: *mut std::ffi::c_void,
+    mac: *const c_char,
+    name: *const c_char,
+) {
+    let Some(callback) = (unsafe { (cb_data as *mut ScanCallback).as_mut() }) else {
+        return;
+    };
+    callback(LeDevice {
+        mac: unsafe { string_from_ptr(mac) }.unwrap_or_default(),
+        name: unsafe { string_from_ptr(name) }.unwrap_or_default(),
+    });
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+impl Drop for LeScan {
+    fn drop(&mut self) {
+        unsafe {
+            self.iv.StopScanBTLE();
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+/// The custom GATT service of this device, which companion apps connect to.
+///
+/// Stopped on drop.
+#[cfg(not(feature = "sdk-5-19"))]
+#[must_use = "the service is stopped when dropped"]
+pub struct GattService {
+    iv: &'static bindings::Inkview,
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+impl GattService {
+    pub fn start(iv: &'static bindings::Inkview, name: &str) -> Result<Self, BluetoothError> {
+        if iv.StartCustomGattService.is_err() || iv.StopCustomGattService.is_err() {
+            return Err(BluetoothError::Unavailable);
+        }
+        let name = c_string(name)?;
+        // Inkview takes a mutable pointer, but does not modify the name.
+        check(unsafe { iv.StartCustomGattService(name.as_ptr() as *mut _) })?;
+        Ok(Self { iv })
+    }
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+impl Drop for GattService {
+    fn drop(&mut self) {
+        unsafe {
+            self.iv.StopCustomGattService();
+        }
+    }
+}
+
+/// The state of a [`GattConnection`].
+#[cfg(not(feature = "sdk-5-19"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GattStatus {
+    Unknown,
+    Disconnected,
+    Error,
+    ConnectionError,
+    ServiceError,
+    Connecting,
+    DiscoveringServices,
+    Connected,
+}
+
+/// A connection to the custom GATT service of another device, closed on drop.
+///
+/// Must only be used on the main thread.
+#[cfg(not(feature = "sdk-5-19"))]
+pub struct GattConnection {
+    iv: &'static bindings::Inkview,
+    session: *mut bindings::CustomGattSession,
+}
+
+// Only moved into timer callbacks, which run on the main thread.
+#[cfg(not(feature = "sdk-5-19"))]
+unsafe impl Send for GattConnection {}
+
+#[cfg(not(feature = "sdk-5-19"))]
+impl GattConnection {
+    /// Maximum length of a GATT attribute value, the required length of the buffer of
+    /// [`GattConnection::read_into`].
+    pub const MAX_VALUE_LEN: usize = 512;
+
+    /// Interval in which [`GattConnection::connect_with`] checks the connection status.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Start connecting to the custom GATT service of the device with the MAC address `mac`.
+    ///
+    /// Check [`GattConnection::status`] before writing, or use
+    /// [`GattConnection::connect_with`].
+    pub fn connect(iv: &'static bindings::Inkview, mac: &str) -> Result<Self, BluetoothError> {
+        if iv.Connect2CustomGattService.is_err() || iv.CloseCustomGattConnection.is_err() {
+            return Err(BluetoothError::Unavailable);
+        }
+        let mac = c_string(mac)?;
+        // Inkview takes a mutable pointer, but does not modify the address.
+        let session = unsafe { iv.Connect2CustomGattService(mac.as_ptr() as *mut _) };
+        if session.is_null() {
+            return Err(BluetoothError::GattConnection);
+        }
+        Ok(Self { iv, session })
+    }
+
+    pub fn status(&self) -> GattStatus {
+        match unsafe { self.iv.GetCustomGattSessionStatus(self.session) } {
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_disconnected => {
+                GattStatus::Disconnected
+            }
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_errorCommon => GattStatus::Error,
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_errorConnection => {
+                GattStatus::ConnectionError
+            }
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_errorService => {
+                GattStatus::ServiceError
+            }
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_connecting => {
+                GattStatus::Connecting
+            }
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_discoveringServices => {
+                GattStatus::DiscoveringServices
+            }
+            bindings::CustomGattSessionStatus_e_GattSessionStatus_connected => {
+                GattStatus::Connected
+            }
+            _ => GattStatus::Unknown,
+        }
+    }
+
+    /// Connect to the custom GATT service of the device with the MAC address `mac`, calling
+    /// `on_done` on the main thread once connected.
+    ///
+    /// Fails with [`BluetoothError::GattConnection`] if the firmware reports an error, and with
+    /// [`BluetoothError::Timeout`] if not connected after `timeout`.
+    pub fn connect_with<F: FnOnce(Result<Self, BluetoothError>) + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        mac: &str,
+        timeout: Duration,
+        on_done: F,
+    ) {
+        match Self::connect(iv, mac) {
+            Ok(connection) => connection.poll_connected(Instant::now() + timeout, on_done),
+            Err(e) => on_done(Err(e)),
+        }
+    }
+
+    fn poll_connected<F: FnOnce(Result<Self, BluetoothError>) + Send + 'static>(
+        self,
+        deadline: Instant,
+        on_done: F,
+    ) {
+        match self.status() {
+            GattStatus::Connected => on_done(Ok(self)),
+            GattStatus::Error | GattStatus::ConnectionError | GattStatus::ServiceError => {
+                on_done(Err(BluetoothError::GattConnection))
+            }
+            _ if Instant::now() >= deadline => on_done(Err(BluetoothError::Timeout)),
+            _ => {
+                let iv = self.iv;
+                Timer::once(iv, Self::POLL_INTERVAL, move || {
+                    self.poll_connected(deadline, on_done)
+                })
+                .detach();
+            }
+        }
+    }
+
+    /// Read the data received from the service into `buf`, returning its length, 0 if there is
+    /// none.
+    ///
+    /// The firmware does not take the length of the buffer, so this assumes that it writes at
+    /// most one GATT value, [`GattConnection::MAX_VALUE_LEN`] bytes as limited by the Bluetooth
+    /// specification. The SDK does not document this. Fails with
+    /// [`BluetoothError::BufferTooSmall`] without reading if `buf` is shorter.
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, BluetoothError> {
+        if self.iv.ReadCustomGattService.is_err() {
+            return Err(BluetoothError::Unavailable);
+        }
+        if buf.len() < Self::MAX_VALUE_LEN {
+            return Err(BluetoothError::BufferTooSmall {
+                len: buf.len(),
+                required: Self::MAX_VALUE_LEN,
+            });
+        }
+        let len = check(unsafe {
+            self.iv
+                .ReadCustomGattService(self.session, buf.as_mut_ptr() as *mut c_char)
+        })?;
+        Ok((len as usize).min(buf.len()))
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), BluetoothError> {
+        check(unsafe {
+            self.iv
+                .WriteCustomGattService(self.session, data.as_ptr() as *const c_char, data.len())
+        })
+        .map(|_| ())
+    }
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+impl Drop for GattConnection {
+    fn drop(&mut self) {
+        unsafe {
+            self.iv.CloseCustomGattConnection(self.session);
+        }
+    }
+}
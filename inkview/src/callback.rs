@@ -0,0 +1,141 @@
+//! Storage for the Rust side of native UI elements.
+//!
+//! Inkview only shows a single dialog, keyboard, menu, list, ... of each kind at a time, and
+//! their handlers take no user data. The callbacks and the memory of the element currently
+//! shown are therefore kept in a static [`Slot`].
+//!
+//! The lock of a slot is never held while calling into user code, so callbacks may open the
+//! next element of the same kind.
+
+use std::sync::Mutex;
+
+/// Holds the value of the single element of a kind that is currently shown.
+pub(crate) struct Slot<T>(Mutex<Option<T>>);
+
+impl<T> Slot<T> {
+    pub(crate) const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    pub(crate) fn take(&self) -> Option<T> {
+        self.0.lock().unwrap().take()
+    }
+
+    /// Store `value`, returning the value it replaced.
+    pub(crate) fn replace(&self, value: T) -> Option<T> {
+        self.0.lock().unwrap().replace(value)
+    }
+
+    /// Put back a value that was taken out while calling into it.
+    ///
+    /// Returns the value if another one was stored meanwhile.
+    pub(crate) fn restore(&self, value: T) -> Option<T> {
+        let mut slot = self.0.lock().unwrap();
+        if slot.is_some() {
+            return Some(value);
+        }
+        *slot = Some(value);
+        None
+    }
+
+    /// Access the stored value, the lock is held while `f` runs.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.0.lock().unwrap().as_mut().map(f)
+    }
+
+    pub(crate) fn is_some(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+}
+
+/// A callback called once with the result of an element, or with `None` if the element was
+/// closed or replaced without a result.
+pub(crate) struct Pending<T> {
+    callback: Box<dyn FnOnce(Option<T>) + Send>,
+    /// Memory the element points into, dropped after the callback was called.
+    _keep_alive: Option<Box<dyn Send>>,
+}
+
+impl<T> Pending<T> {
+    pub(crate) fn new<F: FnOnce(Option<T>) + Send + 'static>(callback: F) -> Self {
+        Self {
+            callback: Box::new(callback),
+            _keep_alive: None,
+        }
+    }
+
+    /// Keep `data` alive until the callback was called.
+    pub(crate) fn keep_alive<D: Send + 'static>(mut self, data: D) -> Self {
+        self._keep_alive = Some(Box::new(data));
+        self
+    }
+
+    pub(crate) fn resolve(self, value: Option<T>) {
+        (self.callback)(value);
+    }
+}
+
+impl<T> Slot<Pending<T>> {
+    /// Store `pending`, resolving the callback it replaces with `None`.
+    pub(crate) fn install(&self, pending: Pending<T>) {
+        if let Some(previous) = self.replace(pending) {
+            previous.resolve(None);
+        }
+    }
+
+    /// Take the stored callback and call it with `value`.
+    pub(crate) fn resolve(&self, value: Option<T>) {
+        if let Some(pending) = self.take() {
+            pending.resolve(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Results = Arc<Mutex<Vec<Option<i32>>>>;
+
+    /// Results of the callbacks, and a constructor for callbacks adding a tag to the value.
+    fn recorder() -> (Results, impl Fn(i32) -> Pending<i32>) {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let pending = {
+            let results = results.clone();
+            move |tag: i32| {
+                let results = results.clone();
+                Pending::new(move |value: Option<i32>| {
+                    results.lock().unwrap().push(value.map(|v| v + tag))
+                })
+            }
+        };
+        (results, pending)
+    }
+
+    #[test]
+    fn install_resolves_replaced_callback_with_none() {
+        static SLOT: Slot<Pending<i32>> = Slot::new();
+        let (results, pending) = recorder();
+
+        SLOT.install(pending(0));
+        SLOT.install(pending(100));
+        assert_eq!(*results.lock().unwrap(), [None]);
+
+        SLOT.resolve(Some(1));
+        SLOT.resolve(Some(2));
+        assert_eq!(*results.lock().unwrap(), [None, Some(101)]);
+    }
+
+    #[test]
+    fn callback_may_install_next_callback() {
+        static SLOT: Slot<Pending<i32>> = Slot::new();
+        let (results, pending) = recorder();
+
+        let next = pending(10);
+        SLOT.install(Pending::new(move |_| SLOT.install(next)));
+        SLOT.resolve(None);
+        SLOT.resolve(Some(1));
+        assert_eq!(*results.lock().unwrap(), [Some(11)]);
+    }
+}
@@ -0,0 +1,1127 @@
+//! Typed access to inkview config files.
+//!
+//! Config files are flat `key=value` files. With the `serde` feature, structs can be stored in
+//! them, nested structs and maps use dotted keys (`outer.inner`).
+
+use crate::bindings;
+use crate::encoding::{c_string, string_from_ptr};
+use crate::error::ConfigError;
+use crate::settings::{RawSettings, Setting};
+use std::path::Path;
+use std::ptr;
+
+/// Write a config to its file.
+///
+/// `SaveConfig` returns 0 if the file couldn't be written.
+pub(crate) fn save(
+    iv: &'static bindings::Inkview,
+    raw: *mut bindings::iconfig,
+) -> Result<(), ConfigError> {
+    if unsafe { iv.SaveConfig(raw) } != 0 {
+        return Ok(());
+    }
+    Err(ConfigError::Save {
+        path: unsafe { string_from_ptr((*raw).filename) }.unwrap_or_default(),
+    })
+}
+
+/// Reads the values of a config, parsing them from their string representation.
+trait ReadValues {
+    fn get_string(&self, key: &str) -> Result<Option<String>, ConfigError>;
+
+    fn get_parsed<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>, ConfigError> {
+        let Some(value) = self.get_string(key)? else {
+            return Ok(None);
+        };
+        match value.trim().parse() {
+            Ok(v) => Ok(Some(v)),
+            Err(_) => Err(ConfigError::InvalidValue {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
+
+    fn get_bool(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        let Some(value) = self.get_string(key)? else {
+            return Ok(None);
+        };
+        match value.trim() {
+            "1" | "true" | "on" | "yes" => Ok(Some(true)),
+            "0" | "false" | "off" | "no" | "" => Ok(Some(false)),
+            _ => Err(ConfigError::InvalidValue {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
+}
+
+/// Writes the values of a config, used by the serde support to store values.
+#[cfg(feature = "serde")]
+trait WriteValues {
+    fn set_string(&mut self, key: &str, value: &str) -> Result<(), ConfigError>;
+
+    fn remove(&mut self, key: &str) -> Result<(), ConfigError>;
+}
+
+/// Read access shared by [`Config`] and [`GlobalConfig`].
+#[derive(Clone, Copy)]
+struct RawConfig {
+    iv: &'static bindings::Inkview,
+    raw: *mut bindings::iconfig,
+}
+
+impl ReadValues for RawConfig {
+    fn get_string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let key = c_string(key)?;
+        let value = unsafe { self.iv.ReadString(self.raw, key.as_ptr(), ptr::null()) };
+        // The returned string is owned by the config, copy it right away.
+        Ok(unsafe { string_from_ptr(value) })
+    }
+}
+
+impl RawConfig {
+    #[cfg(not(feature = "sdk-5-19"))]
+    fn keys(&self) -> Option<Vec<String>> {
+        unsafe extern "C" fn collect(
+            name: *mut std::ffi::c_char,
+            _value: *mut std::ffi::c_void,
+            user_data: *mut std::ffi::c_void,
+        ) -> std::ffi::c_int {
+            let keys = unsafe { &mut *(user_data as *mut Vec<String>) };
+            keys.extend(unsafe { string_from_ptr(name) });
+            0
+        }
+
+        self.iv.EnumerateConfig.as_ref().ok()?;
+        let mut keys = Vec::new();
+        unsafe {
+            self.iv.EnumerateConfig(
+                self.raw,
+                Some(collect),
+                &mut keys as *mut Vec<String> as *mut std::ffi::c_void,
+            );
+        }
+        keys.sort();
+        Some(keys)
+    }
+
+    #[cfg(feature = "sdk-5-19")]
+    fn keys(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    fn entries(&self) -> Option<Vec<(String, String)>> {
+        let entries = self
+            .keys()?
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.get_string(&key).ok()??;
+                Some((key, value))
+            })
+            .collect();
+        Some(entries)
+    }
+}
+
+/// An inkview config file, closed on drop.
+///
+/// Must only be used on the main thread.
+pub struct Config {
+    inner: RawConfig,
+    save_on_drop: bool,
+    /// Kept alive while the config is open, inkview may refer to the defaults.
+    _defaults: Option<RawSettings>,
+}
+
+impl Config {
+    /// Open the config file at `path`, which is created on save if it doesn't exist.
+    pub fn open(
+        iv: &'static bindings::Inkview,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, ConfigError> {
+        Self::open_inner(iv, path.as_ref(), None)
+    }
+
+    /// Open the config file at `path`, using the defaults of `settings` for missing keys.
+    pub fn open_with_defaults(
+        iv: &'static bindings::Inkview,
+        path: impl AsRef<Path>,
+        settings: &[Setting],
+    ) -> Result<Self, ConfigError> {
+        Self::open_inner(iv, path.as_ref(), Some(RawSettings::new(settings)?))
+    }
+
+    fn open_inner(
+        iv: &'static bindings::Inkview,
+        path: &Path,
+        defaults: Option<RawSettings>,
+    ) -> Result<Self, ConfigError> {
+        let path_str = path.to_string_lossy().into_owned();
+        let c_path = c_string(path_str.as_str())?;
+        let ce = defaults.as_ref().map_or(ptr::null_mut(), |d| d.root);
+        let raw = unsafe { iv.OpenConfig(c_path.as_ptr(), ce) };
+        if raw.is_null() {
+            return Err(ConfigError::Open { path: path_str });
+        }
+        Ok(Self {
+            inner: RawConfig { iv, raw },
+            save_on_drop: false,
+            _defaults: defaults,
+        })
+    }
+
+    /// Save the config when it is dropped, if it was changed.
+    ///
+    /// Errors can't be reported from drop, call [`Config::save`] where they matter.
+    pub fn save_on_drop(mut self, save_on_drop: bool) -> Self {
+        self.save_on_drop = save_on_drop;
+        self
+    }
+
+    pub fn get_string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.get_string(key)
+    }
+
+    pub fn get_string_or(
+        &self,
+        key: &str,
+        default: impl Into<String>,
+    ) -> Result<String, ConfigError> {
+        Ok(self.get_string(key)?.unwrap_or_else(|| default.into()))
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, ConfigError> {
+        self.inner.get_parsed(key)
+    }
+
+    pub fn get_float(&self, key: &str) -> Result<Option<f64>, ConfigError> {
+        self.inner.get_parsed(key)
+    }
+
+    /// Read a flag, stored as `1` or `0`.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        self.inner.get_bool(key)
+    }
+
+    pub fn set_string(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let key = c_string(key)?;
+        let value = c_string(value)?;
+        unsafe {
+            self.inner
+                .iv
+                .WriteString(self.inner.raw, key.as_ptr(), value.as_ptr());
+        }
+        Ok(())
+    }
+
+    pub fn set_int(&mut self, key: &str, value: i64) -> Result<(), ConfigError> {
+        match i32::try_from(value) {
+            Ok(value) => {
+                let key = c_string(key)?;
+                unsafe {
+                    self.inner.iv.WriteInt(self.inner.raw, key.as_ptr(), value);
+                }
+                Ok(())
+            }
+            Err(_) => self.set_string(key, &value.to_string()),
+        }
+    }
+
+    pub fn set_float(&mut self, key: &str, value: f64) -> Result<(), ConfigError> {
+        self.set_string(key, &value.to_string())
+    }
+
+    /// Write a flag as `1` or `0`.
+    pub fn set_bool(&mut self, key: &str, value: bool) -> Result<(), ConfigError> {
+        self.set_int(key, value as i64)
+    }
+
+    /// Read a value written with [`Config::set_secret`].
+    pub fn get_secret(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        let key = c_string(key)?;
+        let value = unsafe {
+            self.inner
+                .iv
+                .ReadSecret(self.inner.raw, key.as_ptr(), ptr::null())
+        };
+        Ok(unsafe { string_from_ptr(value) })
+    }
+
+    /// Write a value through `WriteSecret`, using the secret storage of the firmware.
+    pub fn set_secret(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let key = c_string(key)?;
+        let value = c_string(value)?;
+        unsafe {
+            self.inner
+                .iv
+                .WriteSecret(self.inner.raw, key.as_ptr(), value.as_ptr());
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<(), ConfigError> {
+        let key = c_string(key)?;
+        unsafe {
+            self.inner.iv.DeleteString(self.inner.raw, key.as_ptr());
+        }
+        Ok(())
+    }
+
+    /// Whether the config was changed since it was opened or saved.
+    pub fn is_changed(&self) -> bool {
+        unsafe { (*self.inner.raw).changed != 0 }
+    }
+
+    /// All keys in the config, sorted.
+    ///
+    /// Returns `None` if `EnumerateConfig` is not available, which is the case with SDK 5.19.
+    pub fn keys(&self) -> Option<Vec<String>> {
+        self.inner.keys()
+    }
+
+    /// All entries in the config, sorted by key.
+    ///
+    /// Returns `None` if `EnumerateConfig` is not available, which is the case with SDK 5.19.
+    pub fn entries(&self) -> Option<Vec<(String, String)>> {
+        self.inner.entries()
+    }
+
+    /// Write the config to its file.
+    pub fn save(&mut self) -> Result<(), ConfigError> {
+        save(self.inner.iv, self.inner.raw)
+    }
+
+    /// Store `value` in the config, which must serialize to a struct or map.
+    ///
+    /// Fields set to `None` are removed. Sequences are not supported. Requires the `serde`
+    /// feature.
+    #[cfg(feature = "serde")]
+    pub fn store<T: serde::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConfigError> {
+        store(self, value)
+    }
+
+    /// Load a value from the config, which must deserialize from a struct or map.
+    ///
+    /// Fields missing in the config use their default if they have one, see `#[serde(default)]`.
+    /// Nested structs, maps and `Option`s of them are found through [`Config::keys`]. Without
+    /// `EnumerateConfig`, with SDK 5.19, they are treated as missing, only flat structs can be
+    /// loaded. Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn load<T: serde::de::DeserializeOwned>(&self) -> Result<T, ConfigError> {
+        load(&self.inner, self.keys().as_deref())
+    }
+}
+
+impl Drop for Config {
+    fn drop(&mut self) {
+        unsafe {
+            if self.save_on_drop && self.is_changed() {
+                self.inner.iv.SaveConfig(self.inner.raw);
+                self.inner.iv.CloseConfig(self.inner.raw);
+            } else {
+                self.inner.iv.CloseConfigNoSave(self.inner.raw);
+            }
+        }
+    }
+}
+
+/// The global system config, which is owned by inkview and can only be read.
+pub struct GlobalConfig {
+    inner: RawConfig,
+}
+
+impl GlobalConfig {
+    pub fn get(iv: &'static bindings::Inkview) -> Option<Self> {
+        let raw = unsafe { iv.GetGlobalConfig() };
+        (!raw.is_null()).then_some(Self {
+            inner: RawConfig { iv, raw },
+        })
+    }
+
+    pub fn get_string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.inner.get_string(key)
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, ConfigError> {
+        self.inner.get_parsed(key)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        self.inner.get_bool(key)
+    }
+
+    /// All entries in the config, sorted by key.
+    ///
+    /// Returns `None` if `EnumerateConfig` is not available, which is the case with SDK 5.19.
+    pub fn entries(&self) -> Option<Vec<(String, String)>> {
+        self.inner.entries()
+    }
+
+    /// Load a value from the config, see [`Config::load`].
+    #[cfg(feature = "serde")]
+    pub fn load<T: serde::de::DeserializeOwned>(&self) -> Result<T, ConfigError> {
+        load(&self.inner, self.inner.keys().as_deref())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl WriteValues for Config {
+    fn set_string(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        Config::set_string(self, key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), ConfigError> {
+        Config::remove(self, key)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for ConfigError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for ConfigError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+fn child_key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}.{name}")
+    }
+}
+
+#[cfg(feature = "serde")]
+fn store<W: WriteValues, T: serde::Serialize + ?Sized>(
+    config: &mut W,
+    value: &T,
+) -> Result<(), ConfigError> {
+    value.serialize(ValueSerializer {
+        config,
+        key: String::new(),
+    })
+}
+
+/// Serializes a value into the config under `key`.
+#[cfg(feature = "serde")]
+struct ValueSerializer<'a, W> {
+    config: &'a mut W,
+    key: String,
+}
+
+#[cfg(feature = "serde")]
+impl<W: WriteValues> ValueSerializer<'_, W> {
+    fn write(self, value: &str) -> Result<(), ConfigError> {
+        if self.key.is_empty() {
+            return Err(ConfigError::Unsupported(
+                "the top-level value must be a struct or map".to_string(),
+            ));
+        }
+        self.config.set_string(&self.key, value)
+    }
+
+    fn unsupported(what: &str) -> ConfigError {
+        ConfigError::Unsupported(format!("{what} can't be stored in a config"))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, W: WriteValues> serde::Serializer for ValueSerializer<'a, W> {
+    type Ok = ();
+    type Error = ConfigError;
+    type SerializeSeq = serde::ser::Impossible<(), ConfigError>;
+    type SerializeTuple = serde::ser::Impossible<(), ConfigError>;
+    type SerializeTupleStruct = serde::ser::Impossible<(), ConfigError>;
+    type SerializeTupleVariant = serde::ser::Impossible<(), ConfigError>;
+    type SerializeMap = MapSerializer<'a, W>;
+    type SerializeStruct = MapSerializer<'a, W>;
+    type SerializeStructVariant = serde::ser::Impossible<(), ConfigError>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), ConfigError> {
+        self.write(if v { "1" } else { "0" })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), ConfigError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), ConfigError> {
+        self.write(v)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), ConfigError> {
+        Err(Self::unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<(), ConfigError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<(), ConfigError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), ConfigError> {
+        if !self.key.is_empty() {
+            self.config.remove(&self.key)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), ConfigError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), ConfigError> {
+        self.write(variant)
+    }
+
+    fn serialize_newtype_struct<T: serde::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), ConfigError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: serde::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), ConfigError> {
+        Err(Self::unsupported("enum variants with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, ConfigError> {
+        Err(Self::unsupported("sequences"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, ConfigError> {
+        Err(Self::unsupported("tuples"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, ConfigError> {
+        Err(Self::unsupported("tuple structs"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, ConfigError> {
+        Err(Self::unsupported("enum variants with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, ConfigError> {
+        Ok(MapSerializer {
+            config: self.config,
+            prefix: self.key,
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, ConfigError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, ConfigError> {
+        Err(Self::unsupported("enum variants with data"))
+    }
+}
+
+/// Serializes the fields of a struct or map into dotted keys below `prefix`.
+#[cfg(feature = "serde")]
+struct MapSerializer<'a, W> {
+    config: &'a mut W,
+    prefix: String,
+    next_key: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl<W: WriteValues> serde::ser::SerializeMap for MapSerializer<'_, W> {
+    type Ok = ();
+    type Error = ConfigError;
+
+    fn serialize_key<T: serde::Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ConfigError> {
+        let key = match serde_json::to_value(key) {
+            Ok(serde_json::Value::String(key)) => key,
+            Ok(serde_json::Value::Number(key)) => key.to_string(),
+            _ => {
+                return Err(ConfigError::Unsupported(
+                    "map keys must be strings or numbers".to_string(),
+                ))
+            }
+        };
+        self.next_key = Some(child_key(&self.prefix, &key));
+        Ok(())
+    }
+
+    fn serialize_value<T: serde::Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ConfigError> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| ConfigError::Custom("map value without key".to_string()))?;
+        value.serialize(ValueSerializer {
+            config: self.config,
+            key,
+        })
+    }
+
+    fn end(self) -> Result<(), ConfigError> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<W: WriteValues> serde::ser::SerializeStruct for MapSerializer<'_, W> {
+    type Ok = ();
+    type Error = ConfigError;
+
+    fn serialize_field<T: serde::Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), ConfigError> {
+        value.serialize(ValueSerializer {
+            config: self.config,
+            key: child_key(&self.prefix, key),
+        })
+    }
+
+    fn end(self) -> Result<(), ConfigError> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+fn load<R: ReadValues, T: serde::de::DeserializeOwned>(
+    config: &R,
+    keys: Option<&[String]>,
+) -> Result<T, ConfigError> {
+    T::deserialize(ValueDeserializer {
+        config,
+        keys,
+        key: String::new(),
+    })
+}
+
+/// Deserializes the value stored under `key`.
+#[cfg(feature = "serde")]
+struct ValueDeserializer<'a, R> {
+    config: &'a R,
+    /// All keys of the config, if they can be enumerated.
+    keys: Option<&'a [String]>,
+    key: String,
+}
+
+#[cfg(feature = "serde")]
+impl<R: ReadValues> ValueDeserializer<'_, R> {
+    fn value(&self) -> Result<String, ConfigError> {
+        self.config
+            .get_string(&self.key)?
+            .ok_or_else(|| ConfigError::Missing {
+                key: self.key.clone(),
+            })
+    }
+
+    fn parse<T: std::str::FromStr>(&self) -> Result<T, ConfigError> {
+        self.config
+            .get_parsed(&self.key)?
+            .ok_or_else(|| ConfigError::Missing {
+                key: self.key.clone(),
+            })
+    }
+
+    /// Whether there are keys below `key`, never if the keys can't be enumerated.
+    fn has_children(&self) -> bool {
+        let prefix = format!("{}.", self.key);
+        self.keys
+            .is_some_and(|keys| keys.iter().any(|k| k.starts_with(&prefix)))
+    }
+
+    fn is_present(&self) -> Result<bool, ConfigError> {
+        Ok(self.config.get_string(&self.key)?.is_some() || self.has_children())
+    }
+}
+
+#[cfg(feature = "serde")]
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "serde")]
+impl<'de, R: ReadValues> serde::Deserializer<'de> for ValueDeserializer<'_, R> {
+    type Error = ConfigError;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        match self.config.get_string(&self.key)? {
+            Some(value) if !self.key.is_empty() => visitor.visit_string(value),
+            _ => self.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_bool<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        let value = self
+            .config
+            .get_bool(&self.key)?
+            .ok_or_else(|| ConfigError::Missing {
+                key: self.key.clone(),
+            })?;
+        visitor.visit_bool(value)
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        visitor.visit_string(self.value()?)
+    }
+
+    fn deserialize_string<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        visitor.visit_string(self.value()?)
+    }
+
+    fn deserialize_option<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        if self.is_present()? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: serde::de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        let Some(keys) = self.keys else {
+            return Err(ConfigError::Unsupported(
+                "maps can't be loaded without EnumerateConfig".to_string(),
+            ));
+        };
+        let prefix = if self.key.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", self.key)
+        };
+        let mut names: Vec<String> = keys
+            .iter()
+            .filter_map(|k| k.strip_prefix(&prefix))
+            .map(|rest| rest.split('.').next().unwrap_or(rest).to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        visitor.visit_map(MapDeserializer {
+            parent: self,
+            names: names.into_iter(),
+            next_key: None,
+        })
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        // Only fields present in the config are visited, so missing fields can use their default.
+        let mut names = Vec::new();
+        for field in fields {
+            let present = ValueDeserializer {
+                config: self.config,
+                keys: self.keys,
+                key: child_key(&self.key, field),
+            }
+            .is_present()?;
+            if present {
+                names.push(field.to_string());
+            }
+        }
+        visitor.visit_map(MapDeserializer {
+            parent: self,
+            names: names.into_iter(),
+            next_key: None,
+        })
+    }
+
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        use serde::de::IntoDeserializer;
+        visitor.visit_enum(self.value()?.into_deserializer())
+    }
+
+    serde::forward_to_deserialize_any! {
+        bytes byte_buf unit_struct seq tuple tuple_struct identifier ignored_any
+    }
+}
+
+/// Visits the `names` below the key of `parent`.
+#[cfg(feature = "serde")]
+struct MapDeserializer<'a, R> {
+    parent: ValueDeserializer<'a, R>,
+    names: std::vec::IntoIter<String>,
+    next_key: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de, R: ReadValues> serde::de::MapAccess<'de> for MapDeserializer<'_, R> {
+    type Error = ConfigError;
+
+    fn next_key_seed<K: serde::de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, ConfigError> {
+        use serde::de::IntoDeserializer;
+        let Some(name) = self.names.next() else {
+            return Ok(None);
+        };
+        self.next_key = Some(child_key(&self.parent.key, &name));
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: serde::de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, ConfigError> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| ConfigError::Custom("map value without key".to_string()))?;
+        seed.deserialize(ValueDeserializer {
+            config: self.parent.config,
+            keys: self.parent.keys,
+            key,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    /// A config without a file, the values are kept in memory.
+    #[derive(Default)]
+    struct FakeConfig(BTreeMap<String, String>);
+
+    impl ReadValues for FakeConfig {
+        fn get_string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+            Ok(self.0.get(key).cloned())
+        }
+    }
+
+    impl WriteValues for FakeConfig {
+        fn set_string(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+            self.0.insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), ConfigError> {
+            self.0.remove(key);
+            Ok(())
+        }
+    }
+
+    impl FakeConfig {
+        fn keys(&self) -> Vec<String> {
+            self.0.keys().cloned().collect()
+        }
+
+        fn load<T: serde::de::DeserializeOwned>(&self) -> Result<T, ConfigError> {
+            load(self, Some(&self.keys()))
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Window {
+        width: u32,
+        height: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Theme {
+        Day,
+        Night,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct State {
+        name: String,
+        scale: f64,
+        muted: bool,
+        theme: Theme,
+        window: Window,
+        last_file: Option<String>,
+        dialog: Option<Window>,
+        bookmarks: BTreeMap<String, i64>,
+    }
+
+    fn state() -> State {
+        State {
+            name: "reader".to_string(),
+            scale: 1.5,
+            muted: true,
+            theme: Theme::Night,
+            window: Window {
+                width: 800,
+                height: 600,
+            },
+            last_file: None,
+            dialog: Some(Window {
+                width: 300,
+                height: 200,
+            }),
+            bookmarks: BTreeMap::from([("intro".to_string(), 3), ("outro".to_string(), 42)]),
+        }
+    }
+
+    #[test]
+    fn round_trips_nested_structs_options_and_maps() {
+        let mut config = FakeConfig::default();
+        store(&mut config, &state()).unwrap();
+
+        assert_eq!(
+            config.keys(),
+            [
+                "bookmarks.intro",
+                "bookmarks.outro",
+                "dialog.height",
+                "dialog.width",
+                "muted",
+                "name",
+                "scale",
+                "theme",
+                "window.height",
+                "window.width",
+            ]
+        );
+        assert_eq!(config.0["muted"], "1");
+        assert_eq!(config.0["theme"], "Night");
+        assert_eq!(config.0["window.width"], "800");
+        assert_eq!(config.load::<State>().unwrap(), state());
+    }
+
+    #[test]
+    fn none_removes_the_stored_value() {
+        let mut config = FakeConfig::default();
+        config.set_string("last_file", "book.epub").unwrap();
+        store(&mut config, &state()).unwrap();
+
+        assert!(!config.0.contains_key("last_file"));
+        assert_eq!(config.load::<State>().unwrap().last_file, None);
+    }
+
+    #[test]
+    fn missing_fields_use_their_default() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Partial {
+            name: String,
+            #[serde(default)]
+            count: u32,
+            #[serde(default)]
+            window: Option<Window>,
+        }
+
+        let mut config = FakeConfig::default();
+        config.set_string("name", "reader").unwrap();
+        assert_eq!(
+            config.load::<Partial>().unwrap(),
+            Partial {
+                name: "reader".to_string(),
+                count: 0,
+                window: None,
+            }
+        );
+
+        config.remove("name").unwrap();
+        assert!(matches!(
+            config.load::<Partial>(),
+            Err(ConfigError::Custom(msg)) if msg.contains("name")
+        ));
+    }
+
+    #[test]
+    fn invalid_values_are_reported_with_their_key() {
+        let mut config = FakeConfig::default();
+        store(&mut config, &state()).unwrap();
+        config.set_string("window.width", "wide").unwrap();
+
+        assert!(matches!(
+            config.load::<State>(),
+            Err(ConfigError::InvalidValue { key, value }) if key == "window.width" && value == "wide"
+        ));
+    }
+
+    #[test]
+    fn sequences_are_rejected() {
+        #[derive(Serialize, Deserialize)]
+        struct Recent {
+            files: Vec<String>,
+        }
+
+        let mut config = FakeConfig::default();
+        let recent = Recent {
+            files: vec!["a.epub".to_string()],
+        };
+        assert!(matches!(
+            store(&mut config, &recent),
+            Err(ConfigError::Unsupported(_))
+        ));
+
+        config.set_string("files", "a.epub").unwrap();
+        assert!(config.load::<Recent>().is_err());
+    }
+
+    #[test]
+    fn without_keys_only_values_are_found() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Flat {
+            name: String,
+            last_file: Option<String>,
+            dialog: Option<Window>,
+        }
+
+        let mut config = FakeConfig::default();
+        store(&mut config, &state()).unwrap();
+
+        // As with SDK 5.19, where the keys can't be enumerated.
+        assert_eq!(
+            load::<_, Flat>(&config, None).unwrap(),
+            Flat {
+                name: "reader".to_string(),
+                last_file: None,
+                dialog: None,
+            }
+        );
+        assert!(matches!(
+            load::<_, State>(&config, None),
+            Err(ConfigError::Custom(_))
+        ));
+    }
+}
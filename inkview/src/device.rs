@@ -0,0 +1,177 @@
+//! Information about the device hardware and firmware.
+
+use crate::bindings;
+use crate::encoding::string_from_ptr;
+use crate::flags::flags;
+use std::fmt;
+
+flags! {
+    /// A set of hardware capabilities.
+    pub struct Capabilities;
+
+    const TOUCH = 1 << 0;
+    /// The display has a color filter.
+    const COLOR = 1 << 1;
+    const FRONTLIGHT = 1 << 2;
+    const LIGHT_SENSOR = 1 << 3;
+    const AUDIO = 1 << 4;
+    /// A G-sensor for detecting the orientation.
+    const GSENSOR = 1 << 5;
+    /// Physical buttons, like page turn keys.
+    const KEYS = 1 << 6;
+    const JOYSTICK = 1 << 7;
+    const WIFI = 1 << 8;
+    const BLUETOOTH = 1 << 9;
+    const USB_HOST = 1 << 10;
+    const SD_CARD = 1 << 11;
+    const GSM = 1 << 12;
+}
+
+impl Capabilities {
+    const NAMES: [(Self, &'static str); 13] = [
+        (Self::TOUCH, "touch"),
+        (Self::COLOR, "color"),
+        (Self::FRONTLIGHT, "frontlight"),
+        (Self::LIGHT_SENSOR, "light_sensor"),
+        (Self::AUDIO, "audio"),
+        (Self::GSENSOR, "gsensor"),
+        (Self::KEYS, "keys"),
+        (Self::JOYSTICK, "joystick"),
+        (Self::WIFI, "wifi"),
+        (Self::BLUETOOTH, "bluetooth"),
+        (Self::USB_HOST, "usb_host"),
+        (Self::SD_CARD, "sd_card"),
+        (Self::GSM, "gsm"),
+    ];
+
+    /// Names of the contained capabilities, e.g. `"touch"`.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Self::NAMES
+            .iter()
+            .filter(|(capability, _)| self.contains(*capability))
+            .map(|(_, name)| *name)
+    }
+
+    /// Query the capabilities of the device.
+    ///
+    /// SDK 5.19 lacks the `device_has_*` functions, there audio, light sensor, USB host,
+    /// SD card and GSM are never reported.
+    pub fn read(iv: &bindings::Inkview) -> Self {
+        let mut capabilities = Self::empty();
+        unsafe {
+            capabilities.set(Self::TOUCH, iv.QueryTouchpanel() != 0);
+            capabilities.set(Self::GSENSOR, iv.QueryGSensor() != 0);
+            capabilities.set(Self::KEYS, iv.QueryDeviceButtons() != 0);
+            capabilities.set(Self::JOYSTICK, iv.IsJoystickButtonsPresent() != 0);
+            let network = iv.QueryNetwork() as u32;
+            capabilities.set(Self::WIFI, network & bindings::NET_WIFI != 0);
+            capabilities.set(Self::BLUETOOTH, network & bindings::NET_BLUETOOTH != 0);
+        }
+        capabilities.set(Self::FRONTLIGHT, crate::frontlight::has_frontlight(iv));
+        capabilities.set(Self::COLOR, ColorMask::read(iv) != ColorMask::None);
+
+        #[cfg(not(feature = "sdk-5-19"))]
+        {
+            let mut query = |capability, f: &Result<unsafe extern "C" fn() -> bool, _>| {
+                if let Ok(f) = f {
+                    capabilities.set(capability, unsafe { f() });
+                }
+            };
+            query(Self::TOUCH, &iv.device_has_touchpanel);
+            query(Self::GSENSOR, &iv.device_has_gyroscope);
+            query(Self::WIFI, &iv.device_has_wifi);
+            query(Self::BLUETOOTH, &iv.device_has_bluetooth);
+            query(Self::LIGHT_SENSOR, &iv.device_has_lightsensor);
+            query(Self::AUDIO, &iv.device_has_audio);
+            query(Self::USB_HOST, &iv.device_has_usbhost);
+            query(Self::SD_CARD, &iv.device_has_extcard);
+            query(Self::GSM, &iv.device_has_gsm);
+        }
+        capabilities
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+/// Serialized as a list of capability names.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Capabilities {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+/// The color filter of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ColorMask {
+    /// A grayscale display.
+    None,
+    Cfa,
+    Cfa2,
+    /// A mask unknown to this crate.
+    Other(u32),
+}
+
+impl ColorMask {
+    #[cfg(not(feature = "sdk-5-19"))]
+    pub fn read(iv: &bindings::Inkview) -> Self {
+        if iv.device_display_colormask.is_ok() {
+            return match unsafe { iv.device_display_colormask() } {
+                bindings::colormask_t_COLORMASK_NONE => Self::None,
+                bindings::colormask_t_COLORMASK_CFA => Self::Cfa,
+                bindings::colormask_t_COLORMASK_CFA2 => Self::Cfa2,
+                mask => Self::Other(mask),
+            };
+        }
+        Self::None
+    }
+
+    /// SDK 5.19 only supports grayscale displays.
+    #[cfg(feature = "sdk-5-19")]
+    pub fn read(_iv: &bindings::Inkview) -> Self {
+        Self::None
+    }
+}
+
+/// Hardware and firmware information, e.g. for crash reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Info {
+    /// Model name, from `GetDeviceModel`.
+    pub model: String,
+    /// Hardware type, from `GetHardwareType`.
+    pub hardware: String,
+    /// Firmware version, from `GetSoftwareVersion`.
+    pub firmware: String,
+    /// Serial number, if the firmware reports it.
+    pub serial: Option<String>,
+    pub screen_dpi: u32,
+    pub color_mask: ColorMask,
+    pub capabilities: Capabilities,
+}
+
+impl Info {
+    pub fn read(iv: &bindings::Inkview) -> Self {
+        unsafe {
+            Self {
+                model: string_from_ptr(iv.GetDeviceModel()).unwrap_or_default(),
+                hardware: string_from_ptr(iv.GetHardwareType()).unwrap_or_default(),
+                firmware: string_from_ptr(iv.GetSoftwareVersion()).unwrap_or_default(),
+                serial: iv
+                    .GetSerialNumber
+                    .is_ok()
+                    .then(|| string_from_ptr(iv.GetSerialNumber()))
+                    .flatten()
+                    .filter(|s| !s.is_empty()),
+                screen_dpi: iv.get_screen_dpi().max(0) as u32,
+                color_mask: ColorMask::read(iv),
+                capabilities: Capabilities::read(iv),
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use std::ffi::{c_int, CString};
+use std::ffi::c_int;
+use std::ptr;
 use std::time::Duration;
 
 use crate::bindings;
+use crate::callback::{Pending, Slot};
+use crate::encoding::c_string;
+use crate::Error;
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
 pub enum Icon {
@@ -18,17 +22,48 @@ pub fn message(
     title: impl Into<String>,
     text: impl Into<String>,
     timeout: Duration,
-) {
-    let title = CString::new(title.into()).unwrap();
-    let text = CString::new(text.into()).unwrap();
+) -> Result<(), Error> {
+    let title = c_string(title.into())?;
+    let text = c_string(text.into())?;
     let timeout = timeout.as_millis();
 
     unsafe {
         iv.Message(icon as i32, title.as_ptr(), text.as_ptr(), timeout as i32);
     }
+    Ok(())
 }
 
-pub fn dialog(
+/// The button of a dialog that was pressed.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum DialogButton {
+    First,
+    Second,
+    Third,
+}
+
+impl DialogButton {
+    fn from_raw(button: c_int) -> Option<Self> {
+        match button {
+            1 => Some(Self::First),
+            2 => Some(Self::Second),
+            3 => Some(Self::Third),
+            _ => None,
+        }
+    }
+}
+
+static DIALOG: Slot<Pending<DialogButton>> = Slot::new();
+
+extern "C" fn dialog_handler(button: c_int) {
+    DIALOG.resolve(DialogButton::from_raw(button));
+}
+
+/// Show a dialog with two or three buttons.
+///
+/// `on_close` is called with the pressed button, or `None` if the dialog was dismissed
+/// otherwise or replaced by another dialog.
+#[allow(clippy::too_many_arguments)]
+pub fn dialog<F: FnOnce(Option<DialogButton>) + Send + 'static>(
     iv: &bindings::Inkview,
     icon: Icon,
     title: impl Into<String>,
@@ -36,15 +71,17 @@ pub fn dialog(
     button_1: impl Into<String>,
     button_2: impl Into<String>,
     button_3: Option<impl Into<String>>,
-) {
-    let title = CString::new(title.into()).unwrap();
-    let text = CString::new(text.into()).unwrap();
-    let button_1 = CString::new(button_1.into()).unwrap();
-    let button_2 = CString::new(button_2.into()).unwrap();
+    on_close: F,
+) -> Result<(), Error> {
+    let title = c_string(title.into())?;
+    let text = c_string(text.into())?;
+    let button_1 = c_string(button_1.into())?;
+    let button_2 = c_string(button_2.into())?;
+    let button_3 = button_3.map(|b| c_string(b.into())).transpose()?;
 
-    if let Some(button_3) = button_3 {
-        let button_3 = CString::new(button_3.into()).unwrap();
+    DIALOG.install(Pending::new(on_close));
 
+    if let Some(button_3) = button_3 {
         unsafe {
             iv.Dialog3(
                 icon as c_int,
@@ -53,7 +90,7 @@ pub fn dialog(
                 button_1.as_ptr(),
                 button_2.as_ptr(),
                 button_3.as_ptr(),
-                None,
+                Some(dialog_handler),
             )
         }
     } else {
@@ -64,8 +101,71 @@ pub fn dialog(
                 text.as_ptr(),
                 button_1.as_ptr(),
                 button_2.as_ptr(),
-                None,
+                Some(dialog_handler),
             )
         }
     }
+    Ok(())
+}
+
+/// Show a dialog and block until a button was pressed.
+///
+/// Returns `None` if the dialog was dismissed without pressing a button. Can be called from the
+/// event handler, see [`crate::iv_main`] for the events arriving while the dialog is shown.
+pub fn dialog_blocking(
+    iv: &bindings::Inkview,
+    icon: Icon,
+    title: impl Into<String>,
+    text: impl Into<String>,
+    button_1: impl Into<String>,
+    button_2: impl Into<String>,
+    button_3: Option<impl Into<String>>,
+) -> Result<Option<DialogButton>, Error> {
+    let title = c_string(title.into())?;
+    let text = c_string(text.into())?;
+    let button_1 = c_string(button_1.into())?;
+    let button_2 = c_string(button_2.into())?;
+    let button_3 = button_3.map(|b| c_string(b.into())).transpose()?;
+
+    let button = unsafe {
+        iv.DialogSynchro(
+            icon as c_int,
+            title.as_ptr(),
+            text.as_ptr(),
+            button_1.as_ptr(),
+            button_2.as_ptr(),
+            button_3.as_ref().map_or(ptr::null(), |b| b.as_ptr()),
+        )
+    };
+    Ok(DialogButton::from_raw(button))
+}
+
+/// Show a dialog, resolving to the pressed button.
+///
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub fn dialog_async(
+    iv: &bindings::Inkview,
+    icon: Icon,
+    title: impl Into<String>,
+    text: impl Into<String>,
+    button_1: impl Into<String>,
+    button_2: impl Into<String>,
+    button_3: Option<impl Into<String>>,
+) -> Result<crate::executor::Completion<Option<DialogButton>>, Error> {
+    let (complete, completion) = crate::executor::completion();
+    dialog(
+        iv, icon, title, text, button_1, button_2, button_3, complete,
+    )?;
+    Ok(completion)
+}
+
+/// Close the currently shown dialog.
+///
+/// The callback of the dialog is called with `None`.
+pub fn close_dialog(iv: &bindings::Inkview) {
+    unsafe {
+        iv.CloseDialog();
+    }
+    dialog_handler(0);
 }
@@ -0,0 +1,281 @@
+//! String conversions between Rust and inkview.
+//!
+//! Rust strings may contain nul bytes, which C strings can't. The wrappers in this crate
+//! return [`Error::InteriorNul`] for such strings instead of panicking.
+
+use crate::bindings;
+use crate::Error;
+use std::ffi::{c_char, c_int, c_ushort, CStr, CString};
+
+/// Convert `s` into a C string for passing it to inkview.
+pub(crate) fn c_string(s: impl Into<Vec<u8>>) -> Result<CString, Error> {
+    Ok(CString::new(s)?)
+}
+
+/// Owns the C strings and arrays of a tree of C structs, like the `imenu` tree of a menu.
+///
+/// Moving a `CString` or a `Vec` into the tree does not move its heap allocation, so the pointers
+/// handed out stay valid while the tree is alive.
+pub(crate) struct CTree<T> {
+    strings: Vec<CString>,
+    string_arrays: Vec<Vec<*mut c_char>>,
+    arrays: Vec<Vec<T>>,
+}
+
+// The raw pointers only point into heap memory owned by the tree itself.
+unsafe impl<T> Send for CTree<T> {}
+
+impl<T> CTree<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            strings: Vec::new(),
+            string_arrays: Vec::new(),
+            arrays: Vec::new(),
+        }
+    }
+
+    pub(crate) fn string(&mut self, s: &str) -> Result<*mut c_char, Error> {
+        let s = c_string(s)?;
+        let ptr = s.as_ptr() as *mut c_char;
+        self.strings.push(s);
+        Ok(ptr)
+    }
+
+    /// Like [`CTree::string`], but null for an empty string.
+    pub(crate) fn optional_string(&mut self, s: &str) -> Result<*mut c_char, Error> {
+        if s.is_empty() {
+            Ok(std::ptr::null_mut())
+        } else {
+            self.string(s)
+        }
+    }
+
+    /// A null-terminated array of strings.
+    pub(crate) fn string_array<S: AsRef<str>>(
+        &mut self,
+        strings: &[S],
+    ) -> Result<*mut *mut c_char, Error> {
+        let mut ptrs = strings
+            .iter()
+            .map(|s| self.string(s.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        ptrs.push(std::ptr::null_mut());
+        let ptr = ptrs.as_mut_ptr();
+        self.string_arrays.push(ptrs);
+        Ok(ptr)
+    }
+
+    /// Store an array of entries, including the terminating entry if the C side expects one.
+    pub(crate) fn array(&mut self, mut array: Vec<T>) -> *mut T {
+        let ptr = array.as_mut_ptr();
+        self.arrays.push(array);
+        ptr
+    }
+}
+
+/// Check that `s` can be converted into a C string.
+pub(crate) fn check_nul(s: &str) -> Result<(), Error> {
+    match s.find('\0') {
+        Some(position) => Err(Error::InteriorNul { position }),
+        None => Ok(()),
+    }
+}
+
+/// Copy a string returned by inkview, replacing invalid UTF-8.
+///
+/// Returns `None` for a null pointer.
+///
+/// # Safety
+///
+/// `s` must be null or point to a nul-terminated string.
+pub(crate) unsafe fn string_from_ptr(s: *const c_char) -> Option<String> {
+    (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned())
+}
+
+/// Copy a string from a fixed-size C array, which is not necessarily nul-terminated.
+pub(crate) fn string_from_array(s: &[c_char]) -> String {
+    let bytes: Vec<u8> = s
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Remove all nul bytes from `s`, for callers that prefer sanitizing over an error.
+pub fn strip_nul(s: &str) -> String {
+    s.replace('\0', "")
+}
+
+/// Convert `text` in the given encoding (e.g. `"cp1251"`, `"koi8-r"`) to UTF-8, through
+/// `convert_to_utf`.
+///
+/// The text is cut at the first nul byte.
+pub fn convert_to_utf8(
+    iv: &bindings::Inkview,
+    text: &[u8],
+    encoding: &str,
+) -> Result<String, Error> {
+    let text = text.split(|b| *b == 0).next().unwrap_or_default();
+    let src = c_string(text)?;
+    let encoding = c_string(encoding)?;
+    // A single byte never takes more than four bytes in UTF-8.
+    let mut dest = vec![0u8; text.len() * 4 + 1];
+    unsafe {
+        iv.convert_to_utf(
+            src.as_ptr(),
+            dest.as_mut_ptr() as *mut c_char,
+            dest.len().min(c_int::MAX as usize) as c_int,
+            encoding.as_ptr(),
+        );
+    }
+    Ok(from_nul_terminated(&dest))
+}
+
+/// Convert `s` to the UCS-2 representation used by inkview, through `utf2ucs`.
+///
+/// The result does not contain the terminating zero.
+pub fn utf8_to_ucs2(iv: &bindings::Inkview, s: &str) -> Result<Vec<u16>, Error> {
+    utf8_to_ucs2_with(s, |src, dest, len| unsafe {
+        iv.utf2ucs(src, dest, len);
+    })
+}
+
+/// [`utf8_to_ucs2`] with the conversion of `utf2ucs`.
+fn utf8_to_ucs2_with(
+    s: &str,
+    utf2ucs: impl FnOnce(*const c_char, *mut c_ushort, c_int),
+) -> Result<Vec<u16>, Error> {
+    let s = c_string(s)?;
+    let mut ucs = vec![0 as c_ushort; s.as_bytes().len() + 1];
+    utf2ucs(
+        s.as_ptr(),
+        ucs.as_mut_ptr(),
+        ucs.len().min(c_int::MAX as usize) as c_int,
+    );
+    let len = ucs.iter().position(|c| *c == 0).unwrap_or(ucs.len());
+    ucs.truncate(len);
+    Ok(ucs)
+}
+
+/// Convert a UCS-2 string returned by inkview to UTF-8, through `ucs2utf`.
+///
+/// The string is cut at the first zero.
+pub fn ucs2_to_utf8(iv: &bindings::Inkview, ucs: &[u16]) -> String {
+    ucs2_to_utf8_with(ucs, |src, dest, len| unsafe {
+        iv.ucs2utf(src, dest, len);
+    })
+}
+
+/// [`ucs2_to_utf8`] with the conversion of `ucs2utf`.
+fn ucs2_to_utf8_with(
+    ucs: &[u16],
+    ucs2utf: impl FnOnce(*const c_ushort, *mut c_char, c_int),
+) -> String {
+    let len = ucs.iter().position(|c| *c == 0).unwrap_or(ucs.len());
+    let mut src = ucs[..len].to_vec();
+    src.push(0);
+    // A UCS-2 character never takes more than three bytes in UTF-8.
+    let mut dest = vec![0u8; len * 3 + 1];
+    ucs2utf(
+        src.as_ptr(),
+        dest.as_mut_ptr() as *mut c_char,
+        dest.len().min(c_int::MAX as usize) as c_int,
+    );
+    from_nul_terminated(&dest)
+}
+
+fn from_nul_terminated(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Like `utf2ucs`, writing at most `len` characters including the terminating zero.
+    fn fake_utf2ucs(src: *const c_char, dest: *mut c_ushort, len: c_int) {
+        let src = unsafe { CStr::from_ptr(src) }.to_str().unwrap();
+        let ucs: Vec<u16> = src.encode_utf16().chain([0]).collect();
+        assert!(ucs.len() <= len as usize, "buffer too small for {src:?}");
+        unsafe { std::ptr::copy_nonoverlapping(ucs.as_ptr(), dest, ucs.len()) };
+    }
+
+    /// Like `ucs2utf`, writing at most `len` bytes including the terminating nul.
+    fn fake_ucs2utf(src: *const c_ushort, dest: *mut c_char, len: c_int) {
+        let len_ucs = (0..).take_while(|i| unsafe { *src.add(*i) } != 0).count();
+        let ucs = unsafe { std::slice::from_raw_parts(src, len_ucs) };
+        let mut utf8 = String::from_utf16_lossy(ucs).into_bytes();
+        utf8.push(0);
+        assert!(utf8.len() <= len as usize, "buffer too small for {ucs:?}");
+        unsafe { std::ptr::copy_nonoverlapping(utf8.as_ptr(), dest as *mut u8, utf8.len()) };
+    }
+
+    #[test]
+    fn nul_bytes_are_reported_with_their_position() {
+        assert_eq!(check_nul("title"), Ok(()));
+        assert_eq!(check_nul(""), Ok(()));
+        assert_eq!(check_nul("ab\0c"), Err(Error::InteriorNul { position: 2 }));
+        assert_eq!(check_nul("\0"), Err(Error::InteriorNul { position: 0 }));
+
+        assert_eq!(c_string("title").unwrap().as_bytes(), b"title");
+        assert_eq!(c_string("ab\0c"), Err(Error::InteriorNul { position: 2 }));
+
+        let mut tree = CTree::<u8>::new();
+        assert_eq!(tree.string("a\0"), Err(Error::InteriorNul { position: 1 }));
+        assert_eq!(
+            tree.string_array(&["ok", "\0"]),
+            Err(Error::InteriorNul { position: 0 })
+        );
+    }
+
+    #[test]
+    fn strip_nul_removes_all_nul_bytes() {
+        assert_eq!(strip_nul("a\0b\0\0c"), "abc");
+        assert_eq!(strip_nul("abc"), "abc");
+        assert_eq!(check_nul(&strip_nul("\0\0")), Ok(()));
+    }
+
+    #[test]
+    fn string_from_array_stops_at_nul_or_the_end() {
+        let array = |bytes: &[u8]| bytes.iter().map(|b| *b as c_char).collect::<Vec<_>>();
+        assert_eq!(string_from_array(&array(b"abc\0def")), "abc");
+        assert_eq!(string_from_array(&array(b"abc")), "abc");
+        assert_eq!(string_from_array(&array(b"\0abc")), "");
+        assert_eq!(string_from_array(&array("ä€".as_bytes())), "ä€");
+        assert_eq!(string_from_array(&array(b"a\xffb")), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn string_from_ptr_copies_until_nul() {
+        let s = CString::new("abc").unwrap();
+        assert_eq!(
+            unsafe { string_from_ptr(s.as_ptr()) },
+            Some("abc".to_string())
+        );
+        assert_eq!(unsafe { string_from_ptr(std::ptr::null()) }, None);
+    }
+
+    #[test]
+    fn utf8_to_ucs2_fits_the_buffer_and_drops_the_terminator() {
+        for s in ["", "abc", "äöü", "€uro"] {
+            let expected: Vec<u16> = s.encode_utf16().collect();
+            assert_eq!(utf8_to_ucs2_with(s, fake_utf2ucs).unwrap(), expected);
+        }
+        assert_eq!(
+            utf8_to_ucs2_with("a\0b", |_, _, _| unreachable!()),
+            Err(Error::InteriorNul { position: 1 })
+        );
+    }
+
+    #[test]
+    fn ucs2_to_utf8_fits_the_buffer_and_stops_at_zero() {
+        for s in ["", "abc", "äöü", "€uro"] {
+            let ucs: Vec<u16> = s.encode_utf16().collect();
+            assert_eq!(ucs2_to_utf8_with(&ucs, fake_ucs2utf), s);
+        }
+        let ucs: Vec<u16> = "ab\0cd".encode_utf16().collect();
+        assert_eq!(ucs2_to_utf8_with(&ucs, fake_ucs2utf), "ab");
+    }
+}
@@ -5,3 +5,196 @@ pub struct EnumPrimitiveConversionError<S: core::fmt::Debug> {
     pub src_t: String,
     pub dest_t: String,
 }
+
+/// Errors of the safe inkview wrappers.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error("String contains a nul byte at position {position}, so it can't be passed to inkview")]
+    InteriorNul { position: usize },
+}
+
+impl From<std::ffi::NulError> for Error {
+    fn from(e: std::ffi::NulError) -> Self {
+        Self::InteriorNul {
+            position: e.nul_position(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum MenuError {
+    #[error(transparent)]
+    String(#[from] Error),
+    #[error("Creating the context menu failed")]
+    ContextMenu,
+}
+
+/// Errors persisting the app state of [`crate::app::Lifecycle`].
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum StateError {
+    #[error("Restoring app state from '{path}' failed: {message}")]
+    Restore { path: String, message: String },
+    #[error("Saving app state to '{path}' failed: {message}")]
+    Save { path: String, message: String },
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    String(#[from] Error),
+    #[error("Opening config '{path}' failed")]
+    Open { path: String },
+    #[error("Saving config '{path}' failed")]
+    Save { path: String },
+    #[error("Config key '{key}' is missing")]
+    Missing { key: String },
+    #[error("Value '{value}' of config key '{key}' is invalid")]
+    InvalidValue { key: String, value: String },
+    #[error("Unsupported config value: {0}")]
+    Unsupported(String),
+    #[error("{0}")]
+    Custom(String),
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SettingsError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("A settings editor is already open")]
+    AlreadyOpen,
+    #[error("Creating settings directory '{path}' failed: {message}")]
+    Io { path: String, message: String },
+}
+
+impl From<Error> for SettingsError {
+    fn from(e: Error) -> Self {
+        Self::Config(e.into())
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SecretError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Accessing secrets file '{path}' failed: {message}")]
+    Io { path: String, message: String },
+    #[error("Secrets file '{path}' is corrupted or was encrypted on another device")]
+    Corrupted { path: String },
+}
+
+/// Network errors, from the `NET_E*` codes of inkview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum NetError {
+    #[error("Network operation failed")]
+    Failed,
+    #[error("Network operation was aborted")]
+    Aborted,
+    #[error("Network initialization failed")]
+    Init,
+    #[error("Wrong network id")]
+    WrongId,
+    #[error("Network error")]
+    Network,
+    #[error("File error during network operation")]
+    File,
+    #[error("Broken pipe")]
+    Pipe,
+    #[error("Starting the network thread failed")]
+    Thread,
+    #[error("Unsupported protocol")]
+    Protocol,
+    #[error("Invalid URL")]
+    Url,
+    #[error("Resolving the host failed")]
+    Resolve,
+    #[error("Connecting to the host failed")]
+    Connect,
+    #[error("Access denied")]
+    Access,
+    #[error("Not found")]
+    NotFound,
+    #[error("Transfer was incomplete")]
+    Partial,
+    #[error("Connection broken")]
+    Broken,
+    #[error("Network operation timed out")]
+    Timeout,
+    #[error("Server error")]
+    Server,
+    #[error("HTTP error")]
+    Http,
+    #[error("Network hardware error")]
+    Hardware,
+    #[error("Network is not configured")]
+    NotConfigured,
+    #[error("Network configuration is invalid")]
+    BadConfig,
+    #[error("No network device")]
+    NoDevice,
+    #[error("PPP error")]
+    Ppp,
+    #[error("Network is disabled")]
+    Disabled,
+    #[error("DHCP failed")]
+    Dhcp,
+    #[error("Wrong network key")]
+    WrongKey,
+    #[error("Network authentication failed")]
+    Auth,
+    #[error("Flight mode is enabled")]
+    FlightMode,
+    #[error("Unknown network error {0}")]
+    Other(i32),
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum WifiError {
+    #[error(transparent)]
+    String(#[from] Error),
+    #[error(transparent)]
+    Net(#[from] NetError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("Adding networks with this security type is not supported")]
+    UnsupportedSecurity,
+    #[error("Writing network config '{path}' failed: {message}")]
+    Io { path: String, message: String },
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SessionError {
+    #[error(transparent)]
+    String(#[from] Error),
+    #[error(transparent)]
+    Net(#[from] NetError),
+    #[error("Accessing download file '{path}' failed: {message}")]
+    Io { path: String, message: String },
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum BluetoothError {
+    #[error(transparent)]
+    String(#[from] Error),
+    #[error("Bluetooth is not supported by this device or firmware")]
+    Unavailable,
+    #[error("Bluetooth operation failed with code {0}")]
+    Failed(i32),
+    #[error("Connecting to the GATT service failed")]
+    GattConnection,
+    #[error("Buffer of {len} bytes is too small, at least {required} bytes are required")]
+    BufferTooSmall { len: usize, required: usize },
+    #[error("Bluetooth operation timed out")]
+    Timeout,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum TaskError {
+    #[error(transparent)]
+    String(#[from] Error),
+    #[error("Task operation failed with code {0}")]
+    Failed(i32),
+    #[error("Request payload of {0} bytes is too large")]
+    PayloadTooLarge(usize),
+    #[error("Encoding or decoding request payload failed: {0}")]
+    Payload(String),
+}
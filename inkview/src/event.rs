@@ -1,3 +1,4 @@
+use crate::screen::ScreenOrientation;
 use crate::{bindings, error};
 use num_traits::{FromPrimitive, ToPrimitive};
 
@@ -81,17 +82,66 @@ pub enum Event {
     Repaint,
     Hide,
     Exit,
-    Foreground { pid: i32 },
-    Background { pid: i32 },
-    KeyDown { key: Key },
-    KeyRepeat { key: Key },
-    KeyUp { key: Key },
-    PointerDown { x: i32, y: i32 },
-    PointerMove { x: i32, y: i32 },
-    PointerUp { x: i32, y: i32 },
+    Foreground {
+        pid: i32,
+    },
+    Background {
+        pid: i32,
+    },
+    SaveState,
+    /// A config shared between apps was changed, see `NotifyConfigChanged`.
+    ConfigChanged,
+    /// The device is about to power off after inactivity, see
+    /// [`crate::power::postpone_timed_power_off`].
+    PostponeTimedPowerOff,
+    /// The G-sensor detected a new orientation, see [`crate::gsensor::AutoRotation`].
+    OrientationChanged {
+        orientation: ScreenOrientation,
+    },
+    /// A network connection was established, see [`crate::net`].
+    NetConnected,
+    NetDisconnected,
+    /// The device was connected to a computer as USB storage, files on the device may change
+    /// until [`Event::UsbStoreOut`]. Not sent with SDK 5.19.
+    UsbStoreIn,
+    UsbStoreOut,
+    KeyDown {
+        key: Key,
+    },
+    KeyRepeat {
+        key: Key,
+    },
+    KeyUp {
+        key: Key,
+    },
+    PointerDown {
+        x: i32,
+        y: i32,
+    },
+    PointerMove {
+        x: i32,
+        y: i32,
+    },
+    PointerUp {
+        x: i32,
+        y: i32,
+    },
 }
 
 impl Event {
+    /// Whether the event is a key or pointer event.
+    pub(crate) fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Self::KeyDown { .. }
+                | Self::KeyRepeat { .. }
+                | Self::KeyUp { .. }
+                | Self::PointerDown { .. }
+                | Self::PointerMove { .. }
+                | Self::PointerUp { .. }
+        )
+    }
+
     pub(crate) fn from_raw(event: i32, par1: i32, par2: i32) -> Option<Self> {
         let event = match event as u32 {
             bindings::EVT_INIT => Event::Init,
@@ -101,6 +151,18 @@ impl Event {
             bindings::EVT_EXIT => Event::Exit,
             bindings::EVT_FOREGROUND => Event::Foreground { pid: par1 },
             bindings::EVT_BACKGROUND => Event::Background { pid: par1 },
+            bindings::EVT_SAVESTATE => Event::SaveState,
+            bindings::EVT_CONFIGCHANGED => Event::ConfigChanged,
+            bindings::EVT_POSTPONE_TIMED_POWEROFF => Event::PostponeTimedPowerOff,
+            bindings::EVT_ORIENTATION => Event::OrientationChanged {
+                orientation: ScreenOrientation::try_from_iv(par1)?,
+            },
+            bindings::EVT_NET_CONNECTED => Event::NetConnected,
+            bindings::EVT_NET_DISCONNECTED => Event::NetDisconnected,
+            #[cfg(not(feature = "sdk-5-19"))]
+            bindings::EVT_USBSTORE_IN => Event::UsbStoreIn,
+            #[cfg(not(feature = "sdk-5-19"))]
+            bindings::EVT_USBSTORE_OUT => Event::UsbStoreOut,
             bindings::EVT_KEYDOWN => Event::KeyDown {
                 key: Key::try_from(par1 as isize).ok()?,
             },
@@ -0,0 +1,148 @@
+//! Manual event loop pumping, as an alternative to [`crate::iv_main`].
+//!
+//! [`crate::iv_main`] hands control to `InkViewMain` until the app exits. Apps that run their own
+//! loop (a game loop, another UI runtime) can instead create an [`EventLoop`] and pump inkview
+//! events in between their own work, on the same thread.
+//!
+//! This is built on `PrepareForLoop`, `ProcessEventLoop`, `ProcessEventLoopQuick`, `IsAnyEvents`
+//! and `FlushEvents`. They are part of all supported SDKs (5.19, 6.5, 6.8 and 6.10), but may be
+//! missing from the `libinkview.so` of older firmware, in which case [`EventLoop::new`] returns
+//! `None`.
+
+use crate::timer::Timer;
+use crate::{bindings, Event};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static QUEUE: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+
+/// A manually pumped inkview event loop.
+///
+/// Must only be used from the thread that created it.
+pub struct EventLoop {
+    iv: &'static bindings::Inkview,
+}
+
+impl EventLoop {
+    /// Prepare the inkview event loop.
+    ///
+    /// Replaces any handler installed through [`crate::iv_main`]. Returns `None` if the loaded
+    /// `libinkview.so` lacks the required functions.
+    pub fn new(iv: &'static bindings::Inkview) -> Option<Self> {
+        if iv.PrepareForLoop.is_err()
+            || iv.ProcessEventLoop.is_err()
+            || iv.ProcessEventLoopQuick.is_err()
+            || iv.IsAnyEvents.is_err()
+        {
+            return None;
+        }
+
+        crate::set_event_handler(iv, |event| {
+            QUEUE.lock().unwrap().push_back(event);
+            Some(())
+        });
+        unsafe {
+            iv.PrepareForLoop(Some(crate::forward_iv_events));
+        }
+
+        Some(Self { iv })
+    }
+
+    /// Process inkview events until at least one event was received or `timeout` elapsed.
+    ///
+    /// Returns the received events. With a zero timeout, only pending events are processed.
+    pub fn poll_events(&mut self, timeout: Duration) -> Vec<Event> {
+        self.process_pending();
+        if QUEUE.lock().unwrap().is_empty() && !timeout.is_zero() {
+            let deadline = Instant::now() + timeout;
+            wait_until(self.iv, Some(deadline), None, || {
+                !QUEUE.lock().unwrap().is_empty()
+            });
+            self.process_pending();
+        }
+
+        QUEUE.lock().unwrap().drain(..).collect()
+    }
+
+    /// Process inkview events, passing each of them to `f`, until it returns `true`.
+    ///
+    /// Events received before are passed to `f` first.
+    pub fn run_until<F: FnMut(Event) -> bool>(&mut self, mut f: F) {
+        loop {
+            self.process_pending();
+
+            let event = QUEUE.lock().unwrap().pop_front();
+            match event {
+                Some(event) if f(event) => return,
+                Some(_) => {}
+                // Blocks until the next event.
+                None => unsafe {
+                    self.iv.ProcessEventLoop();
+                },
+            }
+        }
+    }
+
+    /// Whether inkview has pending events that were not processed yet.
+    pub fn has_pending_events(&self) -> bool {
+        unsafe { self.iv.IsAnyEvents() != 0 }
+    }
+
+    /// Flush pending inkview events.
+    pub fn flush_events(&mut self) {
+        unsafe {
+            self.iv.FlushEvents();
+        }
+    }
+
+    fn process_pending(&mut self) {
+        process_pending(self.iv);
+    }
+}
+
+fn process_pending(iv: &bindings::Inkview) {
+    unsafe {
+        while iv.IsAnyEvents() != 0 {
+            iv.ProcessEventLoop();
+        }
+        // Handles expired timers without blocking.
+        iv.ProcessEventLoopQuick();
+    }
+}
+
+/// Process inkview events until `done` returns `true` or `deadline` passed, for blocking waits
+/// on the main thread.
+///
+/// Blocks in `ProcessEventLoop` until the next event, `done` is checked after every event. For
+/// conditions that change without an event, `recheck` wakes up the loop periodically.
+///
+/// May be called from within the event handler, see [`crate::iv_main`] for the events arriving
+/// meanwhile. Returns whether `done` returned `true`.
+pub(crate) fn wait_until<F: FnMut() -> bool>(
+    iv: &'static bindings::Inkview,
+    deadline: Option<Instant>,
+    recheck: Option<Duration>,
+    mut done: F,
+) -> bool {
+    // Only there to return from `ProcessEventLoop`, the callbacks have nothing to do.
+    let _deadline = deadline.map(|deadline| {
+        Timer::once(
+            iv,
+            deadline.saturating_duration_since(Instant::now()),
+            || {},
+        )
+    });
+    let _recheck = recheck.map(|interval| Timer::repeating_weak(iv, interval, || {}));
+    loop {
+        if done() {
+            return true;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return false;
+        }
+        unsafe {
+            iv.ProcessEventLoop();
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! A single-threaded async executor running on the inkview main thread.
+//!
+//! Requires the `async` feature.
+//!
+//! Tasks are polled from within the inkview event handler, so they may call into inkview
+//! directly. Wakers can be used from any thread, waking up the event loop through
+//! [`crate::dispatch`].
+
+use crate::{bindings, timer::Timer, Event};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::ThreadId;
+use std::time::Duration;
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+thread_local! {
+    static TASKS: RefCell<BTreeMap<usize, Task>> = const { RefCell::new(BTreeMap::new()) };
+    static POLLING: Cell<bool> = const { Cell::new(false) };
+    static EVENTS: RefCell<EventQueue> = const {
+        RefCell::new(EventQueue {
+            events: VecDeque::new(),
+            waker: None,
+            closed: false,
+            next_seq: 0,
+            delivering: None,
+            pass_on: false,
+        })
+    };
+}
+
+static READY: Mutex<VecDeque<usize>> = Mutex::new(VecDeque::new());
+static NEXT_TASK_ID: AtomicUsize = AtomicUsize::new(1);
+/// The thread the tasks live on, tasks are not `Send`.
+static EXECUTOR_THREAD: OnceLock<ThreadId> = OnceLock::new();
+
+struct EventQueue {
+    /// Events with their sequence number.
+    events: VecDeque<(u64, Event)>,
+    waker: Option<Waker>,
+    closed: bool,
+    next_seq: u64,
+    /// Sequence number of the event the inkview handler is currently delivering.
+    delivering: Option<u64>,
+    /// Whether the event currently delivered was passed on with [`EventStream::pass_on`].
+    pass_on: bool,
+}
+
+struct TaskWaker {
+    id: usize,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        READY.lock().unwrap().push_back(self.id);
+        crate::dispatch(run_ready);
+    }
+}
+
+/// Kick off inkview main, driving the future returned by `main` on the main thread.
+///
+/// `main` receives the stream of inkview events. Blocks until app exit.
+///
+/// Events are handled by the app, unless they are passed on to the firmware with
+/// [`EventStream::pass_on`].
+pub fn run<F, Fut>(iv: &'static bindings::Inkview, main: F)
+where
+    F: FnOnce(EventStream) -> Fut,
+    Fut: Future<Output = ()> + 'static,
+{
+    spawn(main(EventStream { last_seq: None }));
+
+    crate::iv_main(iv, |event| {
+        EVENTS.with_borrow_mut(|queue| {
+            let seq = queue.next_seq;
+            queue.next_seq += 1;
+            queue.events.push_back((seq, event));
+            queue.delivering = Some(seq);
+            queue.pass_on = false;
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        });
+        run_ready();
+        let pass_on = EVENTS.with_borrow_mut(|queue| {
+            queue.delivering = None;
+            queue.pass_on
+        });
+        (!pass_on).then_some(())
+    });
+}
+
+/// Spawn a task onto the executor.
+///
+/// # Panics
+///
+/// Panics if not called on the main thread, the thread which first spawned a task.
+pub fn spawn<F: Future<Output = ()> + 'static>(future: F) {
+    let thread = *EXECUTOR_THREAD.get_or_init(|| std::thread::current().id());
+    assert_eq!(
+        thread,
+        std::thread::current().id(),
+        "executor::spawn must be called on the main thread"
+    );
+    let id = NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed);
+    TASKS.with_borrow_mut(|tasks| tasks.insert(id, Box::pin(future)));
+    READY.lock().unwrap().push_back(id);
+    crate::dispatch(run_ready);
+}
+
+/// Poll all tasks that were woken up.
+///
+/// Does nothing when called re-entrantly from within a task.
+fn run_ready() {
+    if POLLING.replace(true) {
+        return;
+    }
+
+    loop {
+        let Some(id) = READY.lock().unwrap().pop_front() else {
+            break;
+        };
+        // Taken out of the map while polling, so the task can spawn further tasks.
+        let Some(mut task) = TASKS.with_borrow_mut(|tasks| tasks.remove(&id)) else {
+            continue;
+        };
+        let waker = Waker::from(Arc::new(TaskWaker { id }));
+        if task
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+        {
+            TASKS.with_borrow_mut(|tasks| tasks.insert(id, task));
+        }
+    }
+
+    POLLING.set(false);
+}
+
+/// The stream of inkview events.
+///
+/// Ends after [`Event::Exit`] was delivered.
+pub struct EventStream {
+    /// Sequence number of the event returned last.
+    last_seq: Option<u64>,
+}
+
+impl EventStream {
+    /// Wait for the next event.
+    pub async fn next(&mut self) -> Option<Event> {
+        std::future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    /// Let the firmware handle the event returned last as well, e.g. to keep its default
+    /// behaviour for keys the app does not use.
+    ///
+    /// Only has an effect when called before the task awaits anything else, while inkview is
+    /// still delivering the event. Returns whether the event was passed on.
+    pub fn pass_on(&mut self) -> bool {
+        EVENTS.with_borrow_mut(|queue| {
+            let current = self.last_seq.is_some() && queue.delivering == self.last_seq;
+            if current {
+                queue.pass_on = true;
+            }
+            current
+        })
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        EVENTS.with_borrow_mut(|queue| {
+            if let Some((seq, event)) = queue.events.pop_front() {
+                if event == Event::Exit {
+                    queue.closed = true;
+                }
+                self.last_seq = Some(seq);
+                Poll::Ready(Some(event))
+            } else if queue.closed {
+                Poll::Ready(None)
+            } else {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    }
+}
+
+impl futures_core::Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_event(cx)
+    }
+}
+
+/// Future returned by [`sleep`].
+pub struct Sleep {
+    iv: &'static bindings::Inkview,
+    duration: Duration,
+    state: Option<(Timer, Completion<()>)>,
+}
+
+/// Wait until `duration` has elapsed, using a hard inkview timer.
+pub fn sleep(iv: &'static bindings::Inkview, duration: Duration) -> Sleep {
+    Sleep {
+        iv,
+        duration,
+        state: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let (_, fired) = this.state.get_or_insert_with(|| {
+            let (complete, fired) = completion();
+            (
+                Timer::once(this.iv, this.duration, move || complete(())),
+                fired,
+            )
+        });
+        Pin::new(fired).poll(cx)
+    }
+}
+
+/// Future returned by [`wait_for_update`].
+pub struct WaitForUpdate {
+    iv: &'static bindings::Inkview,
+    done: Option<Completion<()>>,
+}
+
+/// Wait until the screen has finished updating.
+///
+/// `WaitForUpdateComplete` blocks a helper thread meanwhile, not the main thread.
+pub fn wait_for_update(iv: &'static bindings::Inkview) -> WaitForUpdate {
+    WaitForUpdate { iv, done: None }
+}
+
+impl Future for WaitForUpdate {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.done.is_none() {
+            if unsafe { this.iv.IsUpdateInProcess() } == 0 {
+                return Poll::Ready(());
+            }
+            let (complete, done) = completion();
+            let iv = this.iv;
+            std::thread::spawn(move || {
+                unsafe {
+                    iv.WaitForUpdateComplete();
+                }
+                complete(());
+            });
+            this.done = Some(done);
+        }
+        Pin::new(this.done.as_mut().unwrap()).poll(cx)
+    }
+}
+
+struct CompletionState<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Future resolving to the value passed to a callback of a callback based API.
+pub struct Completion<T> {
+    state: Arc<Mutex<CompletionState<T>>>,
+}
+
+/// Create a callback and a future resolving to the value the callback is called with.
+pub(crate) fn completion<T: Send + 'static>() -> (impl FnOnce(T) + Send + 'static, Completion<T>) {
+    let state = Arc::new(Mutex::new(CompletionState {
+        value: None,
+        waker: None,
+    }));
+    let complete = {
+        let state = state.clone();
+        move |value| {
+            let mut state = state.lock().unwrap();
+            state.value = Some(value);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    };
+    (complete, Completion { state })
+}
+
+impl<T> Future for Completion<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                // The task may have moved, always keep the waker of the latest poll.
+                if !state
+                    .waker
+                    .as_ref()
+                    .is_some_and(|w| w.will_wake(cx.waker()))
+                {
+                    state.waker = Some(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn completion_wakes_the_waker_of_the_latest_poll() {
+        let (complete, mut completion) = completion::<i32>();
+        let first = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let second = Arc::new(CountingWaker(AtomicUsize::new(0)));
+
+        let mut poll = |waker: &Arc<CountingWaker>| {
+            let waker = Waker::from(waker.clone());
+            Pin::new(&mut completion).poll(&mut Context::from_waker(&waker))
+        };
+        assert_eq!(poll(&first), Poll::Pending);
+        assert_eq!(poll(&second), Poll::Pending);
+        complete(7);
+        assert_eq!(first.0.load(Ordering::SeqCst), 0);
+        assert_eq!(second.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&second), Poll::Ready(7));
+    }
+}
@@ -0,0 +1,195 @@
+//! Frontlight brightness and color temperature.
+//!
+//! There is no ambient-light mode. The SDK can neither read the light sensor nor switch the
+//! automatic brightness of the firmware, so apps that want it have to leave the frontlight
+//! alone and point users to [`Frontlight::open_settings`].
+
+use crate::bindings;
+use crate::timer::Timer;
+use std::ffi::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What the frontlight of the device supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrontlightCapabilities {
+    /// The frontlight version reported by `GetFrontlightVersion`.
+    pub version: i32,
+    /// Whether the color temperature can be changed.
+    pub warmth: bool,
+    /// Whether the device has an ambient light sensor.
+    ///
+    /// Only useful to decide whether to leave brightness control to the firmware, see the
+    /// [module docs](self).
+    pub light_sensor: bool,
+}
+
+/// Whether the device has a frontlight.
+pub fn has_frontlight(iv: &bindings::Inkview) -> bool {
+    #[cfg(not(feature = "sdk-5-19"))]
+    if iv.device_has_frontlight.is_ok() {
+        return unsafe { iv.device_has_frontlight() };
+    }
+    unsafe { iv.GetFrontlightState() >= 0 }
+}
+
+/// The frontlight of the device.
+#[derive(Clone, Copy)]
+pub struct Frontlight {
+    iv: &'static bindings::Inkview,
+}
+
+impl Frontlight {
+    /// Interval in which transitions update the frontlight.
+    const TRANSITION_STEP: Duration = Duration::from_millis(100);
+
+    /// Returns `None` if the device has no frontlight.
+    pub fn new(iv: &'static bindings::Inkview) -> Option<Self> {
+        has_frontlight(iv).then_some(Self { iv })
+    }
+
+    pub fn capabilities(&self) -> FrontlightCapabilities {
+        FrontlightCapabilities {
+            version: unsafe { self.iv.GetFrontlightVersion() },
+            warmth: self.warmth().is_some(),
+            light_sensor: self.has_light_sensor(),
+        }
+    }
+
+    #[cfg(not(feature = "sdk-5-19"))]
+    fn has_light_sensor(&self) -> bool {
+        self.iv.device_lightsensor.is_ok()
+            && unsafe { self.iv.device_lightsensor() }
+                != bindings::lightsensor_id_t_LIGHTSENSOR_NONE
+    }
+
+    #[cfg(feature = "sdk-5-19")]
+    fn has_light_sensor(&self) -> bool {
+        false
+    }
+
+    /// Brightness in percent.
+    pub fn brightness(&self) -> u8 {
+        unsafe { self.iv.GetFrontlightState() }.clamp(0, 100) as u8
+    }
+
+    /// Set the brightness in percent and store it as the user setting.
+    pub fn set_brightness(&self, brightness: u8) {
+        unsafe {
+            self.iv.SetFrontlightState(brightness.min(100) as c_int);
+        }
+    }
+
+    /// Set the brightness in percent without changing the stored user setting.
+    pub fn set_brightness_temporary(&self, brightness: u8) {
+        unsafe {
+            self.iv
+                .SetFrontlightStateEx(brightness.min(100) as c_int, 1);
+        }
+    }
+
+    /// Color temperature in percent, from cold to warm.
+    ///
+    /// Returns `None` if the frontlight does not support changing it.
+    pub fn warmth(&self) -> Option<u8> {
+        let color = unsafe { self.iv.GetFrontlightColor() };
+        (color >= 0).then_some(color.min(100) as u8)
+    }
+
+    /// Set the color temperature in percent, from cold to warm.
+    pub fn set_warmth(&self, warmth: u8) {
+        unsafe {
+            self.iv.SetFrontlightColor(warmth.min(100) as c_int);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        unsafe { self.iv.GetFrontlightEnabled() != 0 }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        unsafe {
+            self.iv.SetFrontlightEnabled(enabled as c_int);
+        }
+    }
+
+    /// Open the frontlight settings of the firmware.
+    pub fn open_settings(&self) {
+        unsafe {
+            self.iv.OpenFrontLightConfig();
+        }
+    }
+
+    /// Smoothly change the brightness, and the warmth if given, over `duration`.
+    ///
+    /// The final values are stored as the user setting. Dropping the returned handle stops the
+    /// transition at its current values.
+    pub fn transition(&self, brightness: u8, warmth: Option<u8>, duration: Duration) -> Transition {
+        let steps = (duration.as_millis() / Self::TRANSITION_STEP.as_millis()).max(1) as u32;
+        let from_brightness = self.brightness();
+        let from_warmth = self.warmth();
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let step = {
+            let frontlight = *self;
+            move |i: u32| {
+                let progress = i as f32 / steps as f32;
+                let lerp = |from: u8, to: u8| {
+                    (from as f32 + (to as f32 - from as f32) * progress).round() as u8
+                };
+                if i < steps {
+                    frontlight.set_brightness_temporary(lerp(from_brightness, brightness));
+                } else {
+                    frontlight.set_brightness(brightness);
+                }
+                if let (Some(from), Some(to)) = (from_warmth, warmth) {
+                    frontlight.set_warmth(lerp(from, to));
+                }
+            }
+        };
+        schedule_step(self.iv, 1, steps, step, cancelled.clone());
+
+        Transition { cancelled }
+    }
+}
+
+fn schedule_step<F: Fn(u32) + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    i: u32,
+    steps: u32,
+    step: F,
+    cancelled: Arc<AtomicBool>,
+) {
+    Timer::once(iv, Frontlight::TRANSITION_STEP, move || {
+        if cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        step(i);
+        if i < steps {
+            schedule_step(iv, i + 1, steps, step, cancelled);
+        }
+    })
+    .detach();
+}
+
+/// A running frontlight transition, stopped on drop.
+///
+/// Use [`Transition::detach`] to let it finish in the background.
+#[must_use]
+pub struct Transition {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Transition {
+    /// Let the transition finish without holding on to the handle.
+    pub fn detach(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Transition {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
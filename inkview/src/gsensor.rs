@@ -0,0 +1,135 @@
+//! G-sensor readings and automatic screen rotation.
+
+use crate::bindings;
+use crate::screen::ScreenOrientation;
+use crate::Event;
+
+/// Whether the device has a G-sensor.
+pub fn is_available(iv: &bindings::Inkview) -> bool {
+    unsafe { iv.QueryGSensor() != 0 }
+}
+
+/// Enable or disable the G-sensor, which also stops orientation events.
+pub fn set_enabled(iv: &bindings::Inkview, enabled: bool) {
+    unsafe {
+        iv.SetGSensorEnabled(enabled);
+    }
+}
+
+/// A raw accelerometer reading, in sensor units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Acceleration {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// Read the accelerometer, e.g. for tilt controls.
+///
+/// Returns `None` if the device has no G-sensor.
+pub fn read(iv: &bindings::Inkview) -> Option<Acceleration> {
+    if !is_available(iv) {
+        return None;
+    }
+    let mut acceleration = Acceleration::default();
+    unsafe {
+        iv.ReadGSensor(
+            &mut acceleration.x,
+            &mut acceleration.y,
+            &mut acceleration.z,
+        );
+    }
+    Some(acceleration)
+}
+
+/// The orientation currently detected by the G-sensor.
+pub fn sensor_orientation(iv: &bindings::Inkview) -> Option<ScreenOrientation> {
+    ScreenOrientation::try_from_iv(unsafe { iv.GetGSensorOrientation() })
+}
+
+/// The orientation of the whole system, `None` if it follows the G-sensor.
+pub fn global_orientation(iv: &bindings::Inkview) -> Option<ScreenOrientation> {
+    ScreenOrientation::try_from_iv(unsafe { iv.GetGlobalOrientation() })
+}
+
+/// Set the orientation of the whole system, `None` to follow the G-sensor.
+pub fn set_global_orientation(iv: &bindings::Inkview, orientation: Option<ScreenOrientation>) {
+    unsafe {
+        iv.SetGlobalOrientation(orientation.map_or(-1, ScreenOrientation::to_iv));
+    }
+}
+
+/// Set the orientation used by apps that don't set their own.
+#[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+pub fn set_default_orientation(iv: &bindings::Inkview, orientation: ScreenOrientation) {
+    unsafe {
+        iv.SetDefaultOrientation(orientation.to_iv());
+    }
+}
+
+/// Apply `orientation`, unless the device is configured to not turn upside down.
+///
+/// Returns the orientation that was applied.
+#[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+pub fn set_orientation_checked(
+    iv: &bindings::Inkview,
+    orientation: ScreenOrientation,
+) -> Option<ScreenOrientation> {
+    ScreenOrientation::try_from_iv(unsafe {
+        iv.CheckAndSetUpSideDownOrientation(orientation.to_iv())
+    })
+}
+
+/// Rotates the app along with the G-sensor.
+///
+/// Feed every event received in the [`crate::iv_main`] handler into [`AutoRotation::handle`],
+/// and update the [`crate::screen::Screen`] geometry and repaint when it returns a new
+/// orientation.
+pub struct AutoRotation {
+    iv: &'static bindings::Inkview,
+    locked: bool,
+}
+
+impl AutoRotation {
+    /// Enable the G-sensor and start following it.
+    pub fn new(iv: &'static bindings::Inkview) -> Self {
+        set_enabled(iv, true);
+        Self { iv, locked: false }
+    }
+
+    /// Keep the current orientation until [`AutoRotation::unlock`] is called.
+    pub fn lock(&mut self) {
+        self.locked = true;
+    }
+
+    /// Follow the G-sensor again, applying the orientation it currently detects.
+    ///
+    /// Returns the new orientation if it changed.
+    pub fn unlock(&mut self) -> Option<ScreenOrientation> {
+        self.locked = false;
+        self.apply(sensor_orientation(self.iv)?)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Apply orientation changes from `event`, returns the new orientation if it changed.
+    pub fn handle(&mut self, event: &Event) -> Option<ScreenOrientation> {
+        match event {
+            Event::OrientationChanged { orientation } if !self.locked => self.apply(*orientation),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, orientation: ScreenOrientation) -> Option<ScreenOrientation> {
+        let current = unsafe { self.iv.GetOrientation() };
+        if current == orientation.to_iv() {
+            return None;
+        }
+        unsafe {
+            self.iv.SetOrientation(orientation.to_iv());
+        }
+        Some(orientation)
+    }
+}
@@ -0,0 +1,281 @@
+//! Native on-screen keyboard text input.
+
+use crate::bindings;
+use crate::callback::{Pending, Slot};
+use crate::encoding::{c_string, check_nul, string_from_ptr};
+use crate::flags::flags;
+use crate::screen::Rect;
+use crate::Error;
+use std::ffi::{c_char, c_int};
+#[cfg(not(feature = "sdk-5-19"))]
+use std::sync::Mutex;
+
+/// The layout of the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyboardKind {
+    #[default]
+    Normal = bindings::KBD_NORMAL as isize,
+    /// Always start with the english layout.
+    EnglishText = bindings::KBD_ENTEXT as isize,
+    Phone = bindings::KBD_PHONE as isize,
+    Numeric = bindings::KBD_NUMERIC as isize,
+    IpAddress = bindings::KBD_IPADDR as isize,
+    Filename = bindings::KBD_FILENAME as isize,
+    Url = bindings::KBD_URL as isize,
+    Date = bindings::KBD_DATE as isize,
+    Time = bindings::KBD_TIME as isize,
+    DateTime = bindings::KBD_DATETIME as isize,
+    Hex = bindings::KBD_HEX as isize,
+    Hours = bindings::KBD_HOURS as isize,
+}
+
+flags! {
+    /// Additional keyboard flags, combined with `|`.
+    #[derive(Debug)]
+    pub struct KeyboardFlags;
+
+    const UPPERCASE = bindings::KBD_UPPER;
+    const LOWERCASE = bindings::KBD_LOWER;
+    const FIRST_UPPERCASE = bindings::KBD_FIRSTUPPER;
+    /// Hide the entered text.
+    const PASSWORD = bindings::KBD_PASSWORD;
+    /// Don't select the initial text.
+    const NO_SELECT = bindings::KBD_NOSELECT;
+    /// Place the keyboard at the top of the screen.
+    const SCREEN_TOP = bindings::KBD_SCREENTOP;
+    const NO_HEADER = bindings::KBD_NOHEADER;
+    /// Default to the english layout.
+    const ENGLISH_DEFAULT = bindings::KBD_EN_DEFAULT;
+}
+
+/// Keeps the buffer edited in-place by the keyboard alive until the keyboard is closed.
+static PENDING: Slot<Pending<String>> = Slot::new();
+
+extern "C" fn keyboard_handler(text: *mut c_char) {
+    // Copied before the pending callback, and the buffer `text` points into, is dropped.
+    let text = unsafe { string_from_ptr(text) };
+    PENDING.resolve(text);
+}
+
+/// Open the keyboard to let the user enter text.
+///
+/// `on_done` is called with the entered text, or `None` if the keyboard was cancelled or
+/// replaced by another prompt.
+/// `max_len` is the maximum length of the text in bytes.
+pub fn prompt<F: FnOnce(Option<String>) + Send + 'static>(
+    iv: &bindings::Inkview,
+    title: impl Into<String>,
+    initial: impl Into<String>,
+    kind: KeyboardKind,
+    max_len: usize,
+    on_done: F,
+) -> Result<(), Error> {
+    prompt_with_flags(
+        iv,
+        title,
+        initial,
+        kind,
+        KeyboardFlags::empty(),
+        max_len,
+        on_done,
+    )
+}
+
+/// Open the keyboard with additional flags.
+///
+/// See [`prompt`].
+pub fn prompt_with_flags<F: FnOnce(Option<String>) + Send + 'static>(
+    iv: &bindings::Inkview,
+    title: impl Into<String>,
+    initial: impl Into<String>,
+    kind: KeyboardKind,
+    flags: KeyboardFlags,
+    max_len: usize,
+    on_done: F,
+) -> Result<(), Error> {
+    let title = c_string(title.into())?;
+    let mut initial = initial.into();
+    check_nul(&initial)?;
+    // Truncate at a char boundary, leaving room for the nul terminator.
+    while initial.len() > max_len {
+        initial.pop();
+    }
+    let mut buffer = vec![0u8; max_len + 1];
+    buffer[..initial.len()].copy_from_slice(initial.as_bytes());
+
+    while let Some(previous) = PENDING.take() {
+        // Make sure the keyboard does not write into the buffer that is about to be dropped.
+        // Not holding the lock, the firmware may call the handler when closing the keyboard.
+        unsafe {
+            iv.CloseKeyboard();
+        }
+        previous.resolve(None);
+    }
+    // The heap allocation of the buffer does not move while it is kept in `PENDING`.
+    let buffer_ptr = buffer.as_mut_ptr() as *mut c_char;
+    PENDING.install(Pending::new(on_done).keep_alive(buffer));
+
+    unsafe {
+        iv.OpenKeyboard(
+            title.as_ptr(),
+            buffer_ptr,
+            max_len as c_int,
+            kind as c_int | flags.bits() as c_int,
+            Some(keyboard_handler),
+        );
+    }
+    Ok(())
+}
+
+/// Open the keyboard, resolving to the entered text.
+///
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub fn prompt_async(
+    iv: &bindings::Inkview,
+    title: impl Into<String>,
+    initial: impl Into<String>,
+    kind: KeyboardKind,
+    max_len: usize,
+) -> Result<crate::executor::Completion<Option<String>>, Error> {
+    let (complete, completion) = crate::executor::completion();
+    prompt(iv, title, initial, kind, max_len, complete)?;
+    Ok(completion)
+}
+
+/// Close the keyboard.
+///
+/// The callback of the prompt is called with `None`.
+pub fn close(iv: &bindings::Inkview) {
+    unsafe {
+        iv.CloseKeyboard();
+    }
+    keyboard_handler(std::ptr::null_mut());
+}
+
+/// Whether the keyboard is currently open.
+pub fn is_open(iv: &bindings::Inkview) -> bool {
+    unsafe { iv.IsKeyboardOpened() != 0 }
+}
+
+/// The screen area covered by the keyboard, so layouts can avoid it.
+///
+/// Returns `None` if the keyboard is not open.
+pub fn rect(iv: &bindings::Inkview) -> Option<Rect> {
+    if !is_open(iv) {
+        return None;
+    }
+    let mut rect = bindings::irect::from(Rect::default());
+    unsafe {
+        iv.GetKeyboardRect(&mut rect);
+    }
+    Some(rect.into())
+}
+
+/// A change of the text entered into the keyboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChange {
+    /// The committed text.
+    pub commit: String,
+    /// The text before the change, always empty with SDK 6.5.
+    pub text_before: String,
+    /// Start of the replaced range.
+    pub replace_from: i32,
+    /// Length of the replaced range.
+    pub replace_length: i32,
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+type TextChangeCallback = Box<dyn FnMut(TextChange) + Send>;
+
+/// The registered text change callback.
+///
+/// `callback` is taken out while it runs, so it can set or clear the callback itself.
+#[cfg(not(feature = "sdk-5-19"))]
+struct TextChangeHandler {
+    callback: Option<TextChangeCallback>,
+}
+
+#[cfg(not(feature = "sdk-5-19"))]
+static TEXT_CHANGE: Mutex<Option<TextChangeHandler>> = Mutex::new(None);
+
+#[cfg(not(feature = "sdk-5-19"))]
+fn notify_text_change(
+    commit_string: *const c_char,
+    text_before: *const c_char,
+    replace_from: c_int,
+    replace_length: c_int,
+) {
+    let to_string = |s: *const c_char| unsafe { string_from_ptr(s) }.unwrap_or_default();
+    let change = TextChange {
+        commit: to_string(commit_string),
+        text_before: to_string(text_before),
+        replace_from,
+        replace_length,
+    };
+    // Not holding the lock while the callback runs.
+    let Some(mut callback) = TEXT_CHANGE
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|h| h.callback.take())
+    else {
+        return;
+    };
+    callback(change);
+
+    // Put it back unless it was cleared or replaced in the meantime.
+    let mut handler = TEXT_CHANGE.lock().unwrap();
+    if let Some(handler) = handler.as_mut().filter(|h| h.callback.is_none()) {
+        handler.callback = Some(callback);
+    }
+}
+
+#[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+extern "C" fn text_change_handler(
+    _context: *mut std::ffi::c_void,
+    commit_string: *const c_char,
+    text_before: *const c_char,
+    replace_from: c_int,
+    replace_length: c_int,
+) {
+    notify_text_change(commit_string, text_before, replace_from, replace_length);
+}
+
+/// SDK 6.5 does not report the text before the change.
+#[cfg(feature = "sdk-6-5")]
+extern "C" fn text_change_handler(
+    _context: *mut std::ffi::c_void,
+    commit_string: *const c_char,
+    replace_from: c_int,
+    replace_length: c_int,
+) {
+    notify_text_change(
+        commit_string,
+        std::ptr::null(),
+        replace_from,
+        replace_length,
+    );
+}
+
+/// Get notified about live text changes while the keyboard is open.
+///
+/// Replaces a previously set callback. Not available with SDK 5.19.
+#[cfg(not(feature = "sdk-5-19"))]
+pub fn on_text_change<F: FnMut(TextChange) + Send + 'static>(iv: &bindings::Inkview, callback: F) {
+    *TEXT_CHANGE.lock().unwrap() = Some(TextChangeHandler {
+        callback: Some(Box::new(callback)),
+    });
+    unsafe {
+        iv.setKeyboardTextChangeCallback(Some(text_change_handler), std::ptr::null_mut());
+    }
+}
+
+/// Remove the callback set with [`on_text_change`].
+#[cfg(not(feature = "sdk-5-19"))]
+pub fn clear_text_change(iv: &bindings::Inkview) {
+    unsafe {
+        iv.setKeyboardTextChangeCallback(None, std::ptr::null_mut());
+    }
+    *TEXT_CHANGE.lock().unwrap() = None;
+}
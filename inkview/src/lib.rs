@@ -1,15 +1,44 @@
 #[cfg(not(feature = "_sdk_selected"))]
 compile_error!("No SDK selected, enable one of the 'sdk-*' features.");
 
+pub mod app;
 pub mod bindings;
+pub mod bluetooth;
+mod callback;
+pub mod config;
+pub mod device;
 pub mod dialogs;
+pub mod encoding;
 pub mod error;
 pub mod event;
+pub mod event_loop;
+#[cfg(feature = "async")]
+pub mod executor;
+mod flags;
+pub mod frontlight;
+pub mod gsensor;
+pub mod keyboard;
+pub mod list;
+pub mod main_thread;
+pub mod menu;
+pub mod net;
+pub mod power;
+pub mod progress;
 pub mod screen;
+#[cfg(feature = "secrets")]
+pub mod secrets;
+pub mod settings;
+pub mod task;
+pub mod timer;
+pub mod wifi;
 
-use std::sync::Mutex;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
 
+pub use error::Error;
 pub use event::Event;
+pub use event_loop::EventLoop;
+pub use main_thread::{dispatch, MainThreadHandle};
 
 pub fn load() -> bindings::Inkview {
     unsafe {
@@ -22,31 +51,131 @@ const RES_EVENT_HANDLED: i32 = 0;
 const RES_EVENT_NOT_HANDLED: i32 = -1;
 const RES_EVENT_ERROR: i32 = -2;
 
-type IvEventHandlerType = Mutex<Option<Box<dyn FnMut(Event) -> Option<()> + Send>>>;
+type IvEventHandler = Box<dyn FnMut(Event) -> Option<()> + Send>;
 
-static IV_EVENT_HANDLER: IvEventHandlerType = Mutex::new(None);
+struct IvEventHandlerSlot {
+    handler: Option<IvEventHandler>,
+    /// Whether the handler is running further up the stack.
+    running: bool,
+    /// Events received while the handler was running.
+    deferred: VecDeque<Event>,
+}
+
+/// The handler is taken out while it runs, as it may pump events in a nested event loop,
+/// e.g. through a blocking dialog. Events arriving meanwhile are deferred until it returned.
+static IV_EVENT_HANDLER: Mutex<IvEventHandlerSlot> = Mutex::new(IvEventHandlerSlot {
+    handler: None,
+    running: false,
+    deferred: VecDeque::new(),
+});
+
+/// The library handle passed to [`iv_main`], used by APIs that are called back from inkview.
+pub(crate) static IV: OnceLock<&'static bindings::Inkview> = OnceLock::new();
 
 /// Kick off inkview main.
 ///
 /// Blocks until app exit.
-pub fn iv_main<F: FnMut(Event) -> Option<()> + Send + 'static>(iv: &bindings::Inkview, handler: F) {
-    unsafe {
-        *IV_EVENT_HANDLER.lock().unwrap() = Some(Box::new(handler));
-        iv.InkViewMain(Some(forward_iv_events))
-    }
+///
+/// While the handler blocks in a nested event loop, e.g. [`dialogs::dialog_blocking`], key and
+/// pointer events are not delivered to it, but left to the default processing of the firmware.
+/// Other events are delivered after the handler returned, and are reported to the firmware as
+/// handled.
+pub fn iv_main<F: FnMut(Event) -> Option<()> + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    handler: F,
+) {
+    set_event_handler(iv, handler);
+    unsafe { iv.InkViewMain(Some(forward_iv_events)) }
+}
+
+fn set_event_handler<F: FnMut(Event) -> Option<()> + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    handler: F,
+) {
+    let _ = IV.set(iv);
+    IV_EVENT_HANDLER.lock().unwrap().handler = Some(Box::new(handler));
 }
 
 extern "C" fn forward_iv_events(event: i32, par1: i32, par2: i32) -> i32 {
-    let mut handler = IV_EVENT_HANDLER.lock().unwrap();
-    let Some(handler) = handler.as_deref_mut() else {
+    main_thread::drain();
+    if main_thread::is_wake_event(event, par1) {
+        return RES_EVENT_HANDLED;
+    }
+
+    let mut slot = IV_EVENT_HANDLER.lock().unwrap();
+    if !slot.running && slot.handler.is_none() {
         return RES_EVENT_ERROR;
-    };
+    }
     let Some(evt) = Event::from_raw(event, par1, par2) else {
         return RES_EVENT_NOT_HANDLED;
     };
+    if slot.running {
+        // Re-entered from a nested event loop. Input can't be claimed before the handler saw it,
+        // other events are seen by the handler once it returned.
+        if evt.is_input() {
+            return RES_EVENT_NOT_HANDLED;
+        }
+        slot.deferred.push_back(evt);
+        return RES_EVENT_HANDLED;
+    }
+    let Some(mut handler) = slot.handler.take() else {
+        return RES_EVENT_ERROR;
+    };
+    slot.running = true;
+    drop(slot);
+
+    let result = handler(evt);
+    loop {
+        let mut slot = IV_EVENT_HANDLER.lock().unwrap();
+        let Some(evt) = slot.deferred.pop_front() else {
+            slot.running = false;
+            // Unless the handler was replaced while it ran.
+            slot.handler.get_or_insert(handler);
+            break;
+        };
+        drop(slot);
+        handler(evt);
+    }
 
-    match handler(evt) {
+    match result {
         Some(()) => RES_EVENT_HANDLED,
         None => RES_EVENT_NOT_HANDLED,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn nested_events_are_deferred_until_the_handler_returned() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        IV_EVENT_HANDLER.lock().unwrap().handler = Some(Box::new({
+            let seen = seen.clone();
+            move |event| {
+                seen.lock().unwrap().push(event);
+                if event == Event::Show {
+                    // A nested event loop, like a blocking dialog, delivering another event.
+                    let res = forward_iv_events(bindings::EVT_REPAINT as i32, 0, 0);
+                    assert_eq!(res, RES_EVENT_HANDLED);
+                    // Input is left to the firmware instead.
+                    let res = forward_iv_events(bindings::EVT_POINTERDOWN as i32, 1, 2);
+                    assert_eq!(res, RES_EVENT_NOT_HANDLED);
+                    seen.lock().unwrap().push(Event::Hide);
+                }
+                Some(())
+            }
+        }));
+
+        assert_eq!(
+            forward_iv_events(bindings::EVT_SHOW as i32, 0, 0),
+            RES_EVENT_HANDLED
+        );
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [Event::Show, Event::Hide, Event::Repaint]
+        );
+        assert!(IV_EVENT_HANDLER.lock().unwrap().handler.is_some());
+    }
+}
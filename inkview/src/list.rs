@@ -0,0 +1,306 @@
+//! Paged list dialogs with custom item drawing.
+
+use crate::bindings;
+use crate::callback::Slot;
+use crate::encoding::c_string;
+use crate::screen::Rect;
+use crate::Error;
+use std::ffi::{c_int, CString};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+/// Provides and draws the items of a [`ListDialog`].
+///
+/// All methods are called on the main thread.
+pub trait ListSource: Send {
+    /// Number of items in the list.
+    fn count(&self) -> usize;
+
+    /// Draw the item at `index` into `rect`, highlighting it if it is `selected`.
+    fn draw_item(&mut self, iv: &bindings::Inkview, index: usize, rect: Rect, selected: bool);
+
+    /// Called when the item at `index` was chosen.
+    fn on_select(&mut self, index: usize);
+
+    /// Called when the list is closed without choosing an item.
+    fn on_exit(&mut self) {}
+
+    /// Called when the menu was requested for the item at `index`, e.g. by a long press.
+    fn on_menu(&mut self, _index: usize) {}
+
+    /// Called before the visible items are drawn.
+    fn begin_paint(&mut self, _iv: &bindings::Inkview) {}
+
+    /// Called after the visible items were drawn.
+    fn end_paint(&mut self, _iv: &bindings::Inkview) {}
+}
+
+/// The platform's paged selection list.
+pub struct ListDialog<S> {
+    title: String,
+    source: S,
+    item_width: Option<i32>,
+    item_height: i32,
+    header_level: Option<i32>,
+    selected: usize,
+}
+
+impl<S: ListSource + 'static> ListDialog<S> {
+    const DEFAULT_ITEM_HEIGHT: i32 = 80;
+
+    pub fn new(title: impl Into<String>, source: S) -> Self {
+        Self {
+            title: title.into(),
+            source,
+            item_width: None,
+            item_height: Self::DEFAULT_ITEM_HEIGHT,
+            header_level: None,
+            selected: 0,
+        }
+    }
+
+    /// Size of a single item, the width defaults to the screen width.
+    pub fn item_size(mut self, width: Option<i32>, height: i32) -> Self {
+        self.item_width = width;
+        self.item_height = height;
+        self
+    }
+
+    /// Set the header level of the list, see `SetListHeaderLevel`.
+    pub fn header_level(mut self, level: i32) -> Self {
+        self.header_level = Some(level);
+        self
+    }
+
+    /// Index of the initially selected item.
+    pub fn selected(mut self, index: usize) -> Self {
+        self.selected = index;
+        self
+    }
+
+    /// Open the list, replacing a list that is currently open.
+    ///
+    /// The source of a replaced list gets [`ListSource::on_exit`].
+    pub fn open(self, iv: &'static bindings::Inkview) -> Result<(), Error> {
+        let title = c_string(self.title)?;
+        let item_width = self
+            .item_width
+            .unwrap_or_else(|| unsafe { iv.ScreenWidth() });
+        let count = self.source.count();
+
+        let previous = LIST.replace(ActiveList {
+            iv,
+            source: Box::new(self.source),
+            item_width,
+            item_height: self.item_height,
+        });
+        if let Some(mut previous) = previous {
+            previous.source.on_exit();
+        }
+
+        unsafe {
+            if let Some(level) = self.header_level {
+                iv.SetListHeaderLevel(level);
+            }
+            iv.OpenList(
+                title.as_ptr(),
+                ptr::null(),
+                item_width,
+                self.item_height,
+                count as c_int,
+                self.selected as c_int,
+                Some(list_handler),
+            );
+        }
+        Ok(())
+    }
+}
+
+struct ActiveList {
+    iv: &'static bindings::Inkview,
+    source: Box<dyn ListSource>,
+    item_width: i32,
+    item_height: i32,
+}
+
+static LIST: Slot<ActiveList> = Slot::new();
+
+extern "C" fn list_handler(action: c_int, x: c_int, y: c_int, idx: c_int, state: c_int) -> c_int {
+    // Taken out while the source is called, so it can open another list.
+    let Some(mut list) = LIST.take() else {
+        return 0;
+    };
+    let index = idx.max(0) as usize;
+    let mut closed = false;
+
+    match action as u32 {
+        bindings::LIST_BEGINPAINT => list.source.begin_paint(list.iv),
+        bindings::LIST_PAINT => {
+            let rect = Rect {
+                x,
+                y,
+                w: list.item_width,
+                h: list.item_height,
+            };
+            list.source.draw_item(list.iv, index, rect, state != 0);
+        }
+        bindings::LIST_ENDPAINT => list.source.end_paint(list.iv),
+        bindings::LIST_OPEN => {
+            list.source.on_select(index);
+            closed = true;
+        }
+        bindings::LIST_MENU => list.source.on_menu(index),
+        bindings::LIST_EXIT => {
+            list.source.on_exit();
+            closed = true;
+        }
+        _ => {}
+    }
+
+    if !closed {
+        if let Some(mut replaced) = LIST.restore(list) {
+            // The source opened another list.
+            replaced.source.on_exit();
+        }
+    }
+    1
+}
+
+/// Name of the default font of inkview, `DEFAULTFONT`.
+pub const DEFAULT_FONT: &str = "LiberationSans";
+
+/// Font size of the items of [`open_choice`] and [`choose_from`].
+pub const DEFAULT_FONT_SIZE: i32 = 32;
+
+/// A list of text items.
+struct TextList<F> {
+    iv: &'static bindings::Inkview,
+    items: Vec<CString>,
+    font_name: CString,
+    font_size: i32,
+    /// Opened on the first paint, closed when the list is dropped.
+    font: *mut bindings::ifont,
+    on_done: Option<F>,
+}
+
+// The font is only accessed from the main thread.
+unsafe impl<F: Send> Send for TextList<F> {}
+
+impl<F> Drop for TextList<F> {
+    fn drop(&mut self) {
+        if !self.font.is_null() {
+            unsafe {
+                self.iv.CloseFont(self.font);
+            }
+        }
+    }
+}
+
+impl<F: FnOnce(Option<usize>) + Send> TextList<F> {
+    fn done(&mut self, choice: Option<usize>) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done(choice);
+        }
+    }
+}
+
+impl<F: FnOnce(Option<usize>) + Send> ListSource for TextList<F> {
+    fn count(&self) -> usize {
+        self.items.len()
+    }
+
+    fn begin_paint(&mut self, iv: &bindings::Inkview) {
+        unsafe {
+            if self.font.is_null() {
+                self.font = iv.OpenFont(self.font_name.as_ptr(), self.font_size, 1);
+            }
+            iv.SetFont(self.font, bindings::BLACK as c_int);
+        }
+    }
+
+    fn draw_item(&mut self, iv: &bindings::Inkview, index: usize, rect: Rect, selected: bool) {
+        let Some(text) = self.items.get(index) else {
+            return;
+        };
+        const PADDING: i32 = 16;
+        unsafe {
+            iv.DrawTextRect(
+                rect.x + PADDING,
+                rect.y,
+                rect.w - 2 * PADDING,
+                rect.h,
+                text.as_ptr(),
+                (bindings::ALIGN_LEFT | bindings::VALIGN_MIDDLE | bindings::DOTS) as c_int,
+            );
+            if selected {
+                iv.DrawSelection(rect.x, rect.y, rect.w, rect.h, bindings::BLACK as c_int);
+            }
+        }
+    }
+
+    fn on_select(&mut self, index: usize) {
+        self.done(Some(index));
+    }
+
+    fn on_exit(&mut self) {
+        self.done(None);
+    }
+}
+
+/// Open a list of text items, calling `on_choice` with the index of the chosen item, or `None`
+/// if the list was closed without a choice.
+///
+/// The items are drawn with [`DEFAULT_FONT`] in [`DEFAULT_FONT_SIZE`].
+pub fn open_choice<F: FnOnce(Option<usize>) + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    title: impl Into<String>,
+    items: &[&str],
+    on_choice: F,
+) -> Result<(), Error> {
+    open_choice_with_font(iv, title, items, DEFAULT_FONT, DEFAULT_FONT_SIZE, on_choice)
+}
+
+/// Open a list of text items drawn with the font `font_name` in `font_size`.
+///
+/// See [`open_choice`].
+pub fn open_choice_with_font<F: FnOnce(Option<usize>) + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    title: impl Into<String>,
+    items: &[&str],
+    font_name: &str,
+    font_size: i32,
+    on_choice: F,
+) -> Result<(), Error> {
+    let source = TextList {
+        iv,
+        items: items
+            .iter()
+            .map(|s| c_string(*s))
+            .collect::<Result<_, _>>()?,
+        font_name: c_string(font_name)?,
+        font_size,
+        font: ptr::null_mut(),
+        on_done: Some(on_choice),
+    };
+    ListDialog::new(title, source).open(iv)
+}
+
+/// Let the user choose from a list of text items, blocking until the list is closed.
+///
+/// Must be called on the main thread, it processes inkview events until the list is closed.
+/// Returns the index of the chosen item.
+pub fn choose_from(
+    iv: &'static bindings::Inkview,
+    title: impl Into<String>,
+    items: &[&str],
+) -> Result<Option<usize>, Error> {
+    let choice = Arc::new(Mutex::new(None));
+    open_choice(iv, title, items, {
+        let choice = choice.clone();
+        move |index| *choice.lock().unwrap() = Some(index)
+    })?;
+
+    crate::event_loop::wait_until(iv, None, None, || choice.lock().unwrap().is_some());
+    let index = choice.lock().unwrap().take().flatten();
+    Ok(index)
+}
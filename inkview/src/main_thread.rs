@@ -0,0 +1,74 @@
+//! Run closures on the inkview main thread.
+//!
+//! Most inkview functions must be called from the thread running `InkViewMain`.
+//! Closures queued here are run by the event handler installed through [`crate::iv_main`]
+//! before the user handler sees the next event.
+
+use crate::bindings;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static QUEUE: Mutex<VecDeque<Job>> = Mutex::new(VecDeque::new());
+
+/// Marker passed as `par1` of the wake-up event, so it can be told apart from `EVT_CALLBACK`
+/// events sent by the firmware itself.
+const WAKE_MARKER: i32 = 0x696e_6b76;
+
+/// A cloneable handle for queueing closures onto the inkview main thread.
+#[derive(Clone, Copy)]
+pub struct MainThreadHandle {
+    iv: &'static bindings::Inkview,
+}
+
+impl MainThreadHandle {
+    pub fn new(iv: &'static bindings::Inkview) -> Self {
+        Self { iv }
+    }
+
+    /// Queue `f` to be run on the main thread and wake up the event loop.
+    pub fn dispatch<F: FnOnce() + Send + 'static>(&self, f: F) {
+        QUEUE.lock().unwrap().push_back(Box::new(f));
+        wake(self.iv);
+    }
+}
+
+/// Queue `f` to be run on the main thread.
+///
+/// The event loop is only woken up once [`crate::iv_main`] was entered, closures dispatched
+/// before that are run when the first event arrives.
+pub fn dispatch<F: FnOnce() + Send + 'static>(f: F) {
+    QUEUE.lock().unwrap().push_back(Box::new(f));
+    if let Some(iv) = crate::IV.get() {
+        wake(iv);
+    }
+}
+
+fn wake(iv: &bindings::Inkview) {
+    unsafe {
+        iv.SendEvent(
+            Some(crate::forward_iv_events),
+            bindings::EVT_CALLBACK as i32,
+            WAKE_MARKER,
+            0,
+        );
+    }
+}
+
+/// Whether the raw event is a wake-up event sent by [`dispatch`].
+pub(crate) fn is_wake_event(event: i32, par1: i32) -> bool {
+    event == bindings::EVT_CALLBACK as i32 && par1 == WAKE_MARKER
+}
+
+/// Run all queued closures.
+///
+/// The queue lock is released while a closure runs, so closures may dispatch further work.
+pub(crate) fn drain() {
+    loop {
+        let Some(job) = QUEUE.lock().unwrap().pop_front() else {
+            return;
+        };
+        job();
+    }
+}
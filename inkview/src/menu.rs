@@ -0,0 +1,448 @@
+//! Native menus and context menus.
+
+use crate::bindings;
+use crate::callback::{Pending, Slot};
+use crate::encoding::CTree;
+use crate::error::MenuError;
+use crate::screen::{Bitmap, Rect};
+use std::ffi::{c_char, c_int, c_short, CString};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MenuItem {
+    Header(String),
+    Item {
+        id: i16,
+        text: String,
+        enabled: bool,
+        checked: bool,
+        icon: Option<Arc<Bitmap>>,
+    },
+    Separator,
+    Submenu {
+        text: String,
+        menu: Menu,
+    },
+}
+
+/// A menu tree.
+///
+/// Item ids are reported to the selection callback and should be positive.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Menu {
+    items: Vec<MenuItem>,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a header line that can't be selected.
+    pub fn header(mut self, text: impl Into<String>) -> Self {
+        self.items.push(MenuItem::Header(text.into()));
+        self
+    }
+
+    pub fn item(mut self, id: i16, text: impl Into<String>) -> Self {
+        self.items.push(MenuItem::Item {
+            id,
+            text: text.into(),
+            enabled: true,
+            checked: false,
+            icon: None,
+        });
+        self
+    }
+
+    /// Add an item shown with an icon.
+    ///
+    /// Menus with icons are opened through `OpenMenuEx`. Context menus don't show icons.
+    pub fn item_with_icon(mut self, id: i16, text: impl Into<String>, icon: Arc<Bitmap>) -> Self {
+        self.items.push(MenuItem::Item {
+            id,
+            text: text.into(),
+            enabled: true,
+            checked: false,
+            icon: Some(icon),
+        });
+        self
+    }
+
+    /// Add an item that is shown, but can't be selected.
+    pub fn inactive(mut self, id: i16, text: impl Into<String>) -> Self {
+        self.items.push(MenuItem::Item {
+            id,
+            text: text.into(),
+            enabled: false,
+            checked: false,
+            icon: None,
+        });
+        self
+    }
+
+    /// Add an item that is marked with a bullet when `checked`.
+    pub fn checked(mut self, id: i16, text: impl Into<String>, checked: bool) -> Self {
+        self.items.push(MenuItem::Item {
+            id,
+            text: text.into(),
+            enabled: true,
+            checked,
+            icon: None,
+        });
+        self
+    }
+
+    pub fn separator(mut self) -> Self {
+        self.items.push(MenuItem::Separator);
+        self
+    }
+
+    pub fn submenu(mut self, text: impl Into<String>, menu: Menu) -> Self {
+        self.items.push(MenuItem::Submenu {
+            text: text.into(),
+            menu,
+        });
+        self
+    }
+
+    fn has_icons(&self) -> bool {
+        self.items.iter().any(|item| match item {
+            MenuItem::Item { icon, .. } => icon.is_some(),
+            MenuItem::Submenu { menu, .. } => menu.has_icons(),
+            _ => false,
+        })
+    }
+
+    /// Open the menu at the given position.
+    ///
+    /// `on_select` is called with the id of the selected item, or `None` if the menu was
+    /// closed without a selection or replaced by another menu.
+    pub fn open<F: FnOnce(Option<i16>) + Send + 'static>(
+        self,
+        iv: &bindings::Inkview,
+        x: i32,
+        y: i32,
+        on_select: F,
+    ) -> Result<(), MenuError> {
+        if self.has_icons() {
+            let raw = RawMenu::<bindings::imenuex>::new(&self)?;
+            let root = raw.root;
+            MENU.install(Pending::new(on_select).keep_alive(raw));
+            unsafe {
+                iv.OpenMenuEx(root, 0, x, y, Some(menu_handler));
+            }
+        } else {
+            let raw = RawMenu::<bindings::imenu>::new(&self)?;
+            let root = raw.root;
+            MENU.install(Pending::new(on_select).keep_alive(raw));
+            unsafe {
+                iv.OpenMenu(root, 0, x, y, Some(menu_handler));
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the menu as context menu for the item at `anchor`.
+    ///
+    /// `on_select` is called with the id of the chosen item, or `None` if the menu was closed
+    /// without a choice or replaced by another context menu.
+    pub fn open_context<F: FnOnce(Option<i16>) + Send + 'static>(
+        self,
+        iv: &bindings::Inkview,
+        anchor: Rect,
+        on_select: F,
+    ) -> Result<(), MenuError> {
+        let mut on_select = Some(on_select);
+        let context = ContextMenu::new(
+            iv,
+            self,
+            anchor,
+            Box::new(move |id| {
+                if let Some(on_select) = on_select.take() {
+                    on_select(id);
+                }
+                true
+            }),
+        )?;
+        let raw = context.raw;
+        replace_context_menu(iv, context);
+        unsafe {
+            iv.OpenContextMenu(raw);
+        }
+        Ok(())
+    }
+
+    /// Register the menu as the long-press context menu of the app, through `SetContextMenu`.
+    ///
+    /// `on_select` is called whenever an item is selected from the context menu, and a last time
+    /// with `None` when it is replaced by another context menu.
+    pub fn set_context<F: FnMut(Option<i16>) + Send + 'static>(
+        self,
+        iv: &bindings::Inkview,
+        anchor: Rect,
+        mut on_select: F,
+    ) -> Result<(), MenuError> {
+        let context = ContextMenu::new(
+            iv,
+            self,
+            anchor,
+            Box::new(move |id| {
+                on_select(id);
+                // Stay registered for the next long-press.
+                false
+            }),
+        )?;
+        let raw = context.raw;
+        replace_context_menu(iv, context);
+        unsafe {
+            iv.SetContextMenu(raw);
+        }
+        Ok(())
+    }
+}
+
+/// An entry of the C representation of a menu, `imenu` or `imenuex`.
+trait MenuEntry: Sized {
+    fn new(
+        type_: u32,
+        index: i16,
+        text: *mut c_char,
+        submenu: *mut Self,
+        icon: &Option<Arc<Bitmap>>,
+    ) -> Self;
+}
+
+impl MenuEntry for bindings::imenu {
+    fn new(
+        type_: u32,
+        index: i16,
+        text: *mut c_char,
+        submenu: *mut Self,
+        _icon: &Option<Arc<Bitmap>>,
+    ) -> Self {
+        Self {
+            type_: type_ as c_short,
+            index,
+            text,
+            submenu,
+        }
+    }
+}
+
+impl MenuEntry for bindings::imenuex {
+    fn new(
+        type_: u32,
+        index: i16,
+        text: *mut c_char,
+        submenu: *mut Self,
+        icon: &Option<Arc<Bitmap>>,
+    ) -> Self {
+        Self {
+            type_: type_ as c_short,
+            index,
+            text,
+            submenu,
+            // Inkview takes a mutable pointer, but does not modify the icon.
+            icon: icon
+                .as_ref()
+                .map_or(ptr::null_mut(), |i| i.as_ptr() as *mut _),
+            reserved: ptr::null_mut(),
+            font: ptr::null(),
+        }
+    }
+}
+
+/// The C representation of a [`Menu`], owning all memory the entries point to.
+struct RawMenu<T> {
+    _tree: CTree<T>,
+    _icons: Vec<Arc<Bitmap>>,
+    root: *mut T,
+}
+
+// The raw pointers only point into heap memory owned by the struct itself.
+unsafe impl<T> Send for RawMenu<T> {}
+
+impl<T: MenuEntry> RawMenu<T> {
+    fn new(menu: &Menu) -> Result<Self, MenuError> {
+        let mut tree = CTree::new();
+        let mut icons = Vec::new();
+        let root = Self::build(menu, &mut tree, &mut icons)?;
+        Ok(Self {
+            _tree: tree,
+            _icons: icons,
+            root,
+        })
+    }
+
+    fn build(
+        menu: &Menu,
+        tree: &mut CTree<T>,
+        icons: &mut Vec<Arc<Bitmap>>,
+    ) -> Result<*mut T, MenuError> {
+        let mut array = Vec::with_capacity(menu.items.len() + 1);
+        for item in &menu.items {
+            let entry = match item {
+                MenuItem::Header(t) => T::new(
+                    bindings::ITEM_HEADER,
+                    0,
+                    tree.string(t)?,
+                    ptr::null_mut(),
+                    &None,
+                ),
+                MenuItem::Item {
+                    id,
+                    text: t,
+                    enabled,
+                    checked,
+                    icon,
+                } => {
+                    let type_ = match (enabled, checked) {
+                        (false, _) => bindings::ITEM_INACTIVE,
+                        (true, true) => bindings::ITEM_BULLET,
+                        (true, false) => bindings::ITEM_ACTIVE,
+                    };
+                    icons.extend(icon.iter().cloned());
+                    T::new(type_, *id, tree.string(t)?, ptr::null_mut(), icon)
+                }
+                MenuItem::Separator => T::new(
+                    bindings::ITEM_SEPARATOR,
+                    0,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    &None,
+                ),
+                MenuItem::Submenu { text: t, menu } => {
+                    let t = tree.string(t)?;
+                    let submenu = Self::build(menu, tree, icons)?;
+                    T::new(bindings::ITEM_SUBMENU, 0, t, submenu, &None)
+                }
+            };
+            array.push(entry);
+        }
+        // Terminating entry.
+        array.push(T::new(0, 0, ptr::null_mut(), ptr::null_mut(), &None));
+        Ok(tree.array(array))
+    }
+}
+
+fn selected_id(index: c_int) -> Option<i16> {
+    (index > 0).then_some(index as i16)
+}
+
+/// Keeps the memory of the open menu alive.
+static MENU: Slot<Pending<i16>> = Slot::new();
+
+extern "C" fn menu_handler(index: c_int) {
+    MENU.resolve(selected_id(index));
+}
+
+/// Open the 3x3 menu of the firmware, showing the cells of `bitmap` labelled with `labels`.
+///
+/// `on_select` is called with the index of the chosen cell, row by row from `0` to `8`, or
+/// `None` if the menu was closed without a choice or replaced by another menu.
+pub fn open_3x3<F: FnOnce(Option<u8>) + Send + 'static>(
+    iv: &bindings::Inkview,
+    bitmap: Arc<Bitmap>,
+    labels: [&str; 9],
+    on_select: F,
+) -> Result<(), MenuError> {
+    let mut tree = CTree::new();
+    let labels = labels
+        .iter()
+        .map(|label| Ok(tree.string(label)? as *const c_char))
+        .collect::<Result<Vec<_>, MenuError>>()?;
+    let labels = tree.array(labels);
+    let bitmap_ptr = bitmap.as_ptr();
+
+    MENU_3X3.install(
+        Pending::new(move |index: Option<c_int>| {
+            on_select(index.filter(|i| (0..9).contains(i)).map(|i| i as u8))
+        })
+        .keep_alive((tree, bitmap)),
+    );
+    unsafe {
+        iv.OpenMenu3x3(bitmap_ptr, labels, Some(menu_3x3_handler));
+    }
+    Ok(())
+}
+
+/// Keeps the labels and the bitmap of the open 3x3 menu alive.
+static MENU_3X3: Slot<Pending<c_int>> = Slot::new();
+
+extern "C" fn menu_3x3_handler(index: c_int) {
+    MENU_3X3.resolve(Some(index));
+}
+
+/// Returns whether the context menu should be removed after the selection.
+type ContextCallback = Box<dyn FnMut(Option<i16>) -> bool + Send>;
+
+struct ContextMenu {
+    _menu: RawMenu<bindings::imenu>,
+    _id: CString,
+    raw: *mut bindings::icontext_menu,
+    callback: Option<ContextCallback>,
+}
+
+// The context menu is allocated by inkview and only accessed through the mutex.
+unsafe impl Send for ContextMenu {}
+
+impl ContextMenu {
+    fn new(
+        iv: &bindings::Inkview,
+        menu: Menu,
+        anchor: Rect,
+        callback: ContextCallback,
+    ) -> Result<Self, MenuError> {
+        let menu = RawMenu::new(&menu)?;
+        let id = CString::new("inkview-rs").unwrap();
+        let raw = unsafe { iv.CreateContextMenu(id.as_ptr()) };
+        let context = unsafe { raw.as_mut() }.ok_or(MenuError::ContextMenu)?;
+        context.menu = menu.root;
+        context.hproc = Some(context_menu_handler);
+        context.pos_selected_item = anchor.into();
+        Ok(Self {
+            _menu: menu,
+            _id: id,
+            raw,
+            callback: Some(callback),
+        })
+    }
+}
+
+static CONTEXT_MENU: Mutex<Option<ContextMenu>> = Mutex::new(None);
+
+fn replace_context_menu(iv: &bindings::Inkview, context: ContextMenu) {
+    let previous = CONTEXT_MENU.lock().unwrap().replace(context);
+    if let Some(mut previous) = previous {
+        unsafe {
+            iv.CloseContextMenu(previous.raw);
+        }
+        // Not holding the lock, so the callback can open another menu. The callback is missing
+        // if the menu is replaced from within it.
+        if let Some(mut callback) = previous.callback.take() {
+            callback(None);
+        }
+    }
+}
+
+extern "C" fn context_menu_handler(index: c_int) {
+    // Not holding the lock while the callback runs, so it can open another menu.
+    let Some(mut callback) = CONTEXT_MENU
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|c| c.callback.take())
+    else {
+        return;
+    };
+    let remove = callback(selected_id(index));
+
+    let mut context = CONTEXT_MENU.lock().unwrap();
+    if let Some(context) = context.as_mut().filter(|c| c.callback.is_none()) {
+        if !remove {
+            context.callback = Some(callback);
+        }
+    }
+}
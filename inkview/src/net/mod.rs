@@ -0,0 +1,332 @@
+//! Network connection management.
+//!
+//! The firmware reports connection changes as [`crate::Event::NetConnected`] and
+//! [`crate::Event::NetDisconnected`]. HTTP transfers go through the download manager of the
+//! firmware with [`Session`], which respects the proxy and connection settings of the user.
+
+mod session;
+
+pub use session::{Body, Destination, Progress, Response, Session, Transfer, TransferStatus};
+
+use crate::bindings;
+use crate::callback::{Pending, Slot};
+use crate::encoding::{string_from_array, string_from_ptr};
+use crate::error::NetError;
+use std::ffi::{c_char, c_int};
+use std::net::Ipv4Addr;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+impl NetError {
+    const CODES: [(c_int, NetError); 29] = [
+        (bindings::NET_FAIL, Self::Failed),
+        (bindings::NET_ABORTED, Self::Aborted),
+        (bindings::NET_EINIT, Self::Init),
+        (bindings::NET_EWRONGID, Self::WrongId),
+        (bindings::NET_ENETWORK, Self::Network),
+        (bindings::NET_EFILE, Self::File),
+        (bindings::NET_EPIPE, Self::Pipe),
+        (bindings::NET_ETHREAD, Self::Thread),
+        (bindings::NET_EPROTO, Self::Protocol),
+        (bindings::NET_EURL, Self::Url),
+        (bindings::NET_ERESOLVE, Self::Resolve),
+        (bindings::NET_ECONNECT, Self::Connect),
+        (bindings::NET_EACCESS, Self::Access),
+        (bindings::NET_ENOTFOUND, Self::NotFound),
+        (bindings::NET_EPARTIAL, Self::Partial),
+        (bindings::NET_EBROKEN, Self::Broken),
+        (bindings::NET_ETIMEOUT, Self::Timeout),
+        (bindings::NET_ESERVER, Self::Server),
+        (bindings::NET_EHTTP, Self::Http),
+        (bindings::NET_EHARDWARE, Self::Hardware),
+        (bindings::NET_ENOTCONF, Self::NotConfigured),
+        (bindings::NET_EBADCONF, Self::BadConfig),
+        (bindings::NET_ENODEVICE, Self::NoDevice),
+        (bindings::NET_EPPP, Self::Ppp),
+        (bindings::NET_EDISABLED, Self::Disabled),
+        (bindings::NET_EDHCP, Self::Dhcp),
+        (bindings::NET_EWRONGKEY, Self::WrongKey),
+        (bindings::NET_EAUTH, Self::Auth),
+        // NET_EFLIGHTMODE, missing in the 5.19 headers.
+        (-39, Self::FlightMode),
+    ];
+
+    /// The error for a status code returned by inkview, `None` if it indicates success.
+    pub fn from_code(code: i32) -> Option<Self> {
+        if code >= 0 {
+            return None;
+        }
+        Some(
+            Self::CODES
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map_or(Self::Other(code), |(_, e)| *e),
+        )
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Other(code) => *code,
+            e => Self::CODES.iter().find(|(_, c)| c == e).unwrap().0,
+        }
+    }
+
+    /// The localized error message of the firmware, through `NetError`.
+    pub fn localized_message(&self, iv: &bindings::Inkview) -> Option<String> {
+        unsafe { string_from_ptr(iv.NetError(self.code())) }
+    }
+}
+
+pub(crate) fn check(code: c_int) -> Result<(), NetError> {
+    NetError::from_code(code).map_or(Ok(()), Err)
+}
+
+/// The state of the network connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetState {
+    Unknown,
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+pub fn state(iv: &bindings::Inkview) -> NetState {
+    match unsafe { iv.GetNetState() } {
+        bindings::NET_STATE_DISCONNECTED => NetState::Disconnected,
+        bindings::NET_STATE_CONNECTING => NetState::Connecting,
+        bindings::NET_STATE_CONNECTED => NetState::Connected,
+        _ => NetState::Unknown,
+    }
+}
+
+pub fn is_online(iv: &bindings::Inkview) -> bool {
+    state(iv) == NetState::Connected
+}
+
+/// The error of the last failed connection attempt.
+pub fn last_error(iv: &bindings::Inkview) -> Option<NetError> {
+    NetError::from_code(unsafe { iv.GetLastNetConnectionError() })
+}
+
+/// A network connection, disconnected on drop.
+///
+/// If the device was already online when connecting, the connection is left up on drop, as
+/// it is likely used by someone else.
+#[must_use = "the network is disconnected when the connection is dropped"]
+pub struct Connection {
+    iv: &'static bindings::Inkview,
+    disconnect: bool,
+}
+
+impl Connection {
+    fn new(iv: &'static bindings::Inkview, was_online: bool) -> Self {
+        Self {
+            iv,
+            disconnect: !was_online,
+        }
+    }
+
+    /// Keep the network up after the connection is dropped.
+    pub fn keep_alive(mut self) {
+        self.disconnect = false;
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.disconnect {
+            unsafe {
+                self.iv.NetDisconnect();
+            }
+        }
+    }
+}
+
+/// Connect to the default network, blocking until connected.
+///
+/// Unless `silent`, the firmware shows its connection dialog, which lets the user pick a
+/// network or turn on Wi-Fi.
+pub fn connect(iv: &'static bindings::Inkview, silent: bool) -> Result<Connection, NetError> {
+    let was_online = is_online(iv);
+    let code = unsafe {
+        if silent {
+            iv.NetConnectSilent(ptr::null())
+        } else {
+            iv.NetConnect(ptr::null())
+        }
+    };
+    check(code)?;
+    Ok(Connection::new(iv, was_online))
+}
+
+static CONNECT: Slot<Pending<c_int>> = Slot::new();
+
+unsafe extern "C" fn connect_handler(status: c_int) -> c_int {
+    CONNECT.resolve(Some(status));
+    0
+}
+
+/// Connect to the default network in the background, calling `on_done` when finished.
+///
+/// Starting another connection before this one finished calls its `on_done` with
+/// [`NetError::Aborted`]. If starting fails, the error is returned and `on_done` is not called.
+pub fn connect_with<F: FnOnce(Result<Connection, NetError>) + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    on_done: F,
+) -> Result<(), NetError> {
+    let was_online = is_online(iv);
+    CONNECT.install(Pending::new(move |status: Option<c_int>| {
+        let result = match status {
+            Some(status) => check(status).map(|_| Connection::new(iv, was_online)),
+            None => Err(NetError::Aborted),
+        };
+        on_done(result)
+    }));
+    let code = unsafe { iv.NetConnectAsync(Some(connect_handler)) };
+    if let Err(e) = check(code) {
+        drop(CONNECT.take());
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Connect to the default network in the background, resolving when finished.
+///
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub fn connect_async(
+    iv: &'static bindings::Inkview,
+) -> Result<crate::executor::Completion<Result<Connection, NetError>>, NetError> {
+    let (complete, completion) = crate::executor::completion();
+    connect_with(iv, complete)?;
+    Ok(completion)
+}
+
+/// Interval in which the waiting functions check the network state.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Block until the device is online, processing inkview events meanwhile.
+///
+/// Must be called on the main thread, it may be called from the event handler. Fails with
+/// [`NetError::Timeout`] after `timeout`.
+pub fn wait_until_online(
+    iv: &'static bindings::Inkview,
+    timeout: Duration,
+) -> Result<(), NetError> {
+    let deadline = Instant::now() + timeout;
+    if crate::event_loop::wait_until(iv, Some(deadline), Some(POLL_INTERVAL), || is_online(iv)) {
+        Ok(())
+    } else {
+        Err(NetError::Timeout)
+    }
+}
+
+/// Resolves when the device is online, or with [`NetError::Timeout`] after `timeout`.
+///
+/// Requires the `async` feature.
+#[cfg(feature = "async")]
+pub fn wait_until_online_async(
+    iv: &'static bindings::Inkview,
+    timeout: Duration,
+) -> crate::executor::Completion<Result<(), NetError>> {
+    let (complete, completion) = crate::executor::completion();
+    poll_online(iv, Instant::now() + timeout, complete);
+    completion
+}
+
+#[cfg(feature = "async")]
+fn poll_online<F: FnOnce(Result<(), NetError>) + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    deadline: Instant,
+    complete: F,
+) {
+    if is_online(iv) {
+        complete(Ok(()));
+    } else if Instant::now() >= deadline {
+        complete(Err(NetError::Timeout));
+    } else {
+        crate::timer::Timer::once(iv, POLL_INTERVAL, move || {
+            poll_online(iv, deadline, complete)
+        })
+        .detach();
+    }
+}
+
+/// Addresses of a network interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    pub ip: Option<Ipv4Addr>,
+    pub netmask: Option<Ipv4Addr>,
+    pub broadcast: Option<Ipv4Addr>,
+    pub hw_address: String,
+}
+
+fn raw_interface(name: &str) -> Option<bindings::network_interface> {
+    let mut interface = bindings::network_interface { hw_addr: [0; 18] };
+    let intr = unsafe { &mut interface.intr };
+    // Keep the terminating nul.
+    if name.len() >= intr.len() || name.contains('\0') {
+        return None;
+    }
+    for (dst, src) in intr.iter_mut().zip(name.bytes()) {
+        *dst = src as c_char;
+    }
+    Some(interface)
+}
+
+fn parse_addr(interface: &bindings::network_interface) -> Option<Ipv4Addr> {
+    string_from_array(unsafe { &interface.addr }).parse().ok()
+}
+
+/// Addresses of the interface `name`, e.g. `"wlan0"`.
+///
+/// Returns `None` if the interface does not exist.
+pub fn interface_info(iv: &bindings::Inkview, name: &str) -> Option<InterfaceInfo> {
+    let interface = raw_interface(name)?;
+    let info = unsafe { iv.GetNetInfo(&interface).as_ref() }?;
+    Some(InterfaceInfo {
+        ip: parse_addr(&info.ip_addr),
+        netmask: parse_addr(&info.mask),
+        broadcast: parse_addr(&info.br_addr),
+        hw_address: string_from_array(unsafe { &info.hw_addr.hw_addr }),
+    })
+}
+
+/// The gateway of the interface `name`, e.g. `"wlan0"`.
+pub fn gateway(iv: &bindings::Inkview, name: &str) -> Option<Ipv4Addr> {
+    let interface = raw_interface(name)?;
+    parse_addr(unsafe { iv.GetNetGateway(&interface).as_ref() }?)
+}
+
+/// The configured DNS servers.
+pub fn dns_servers(iv: &bindings::Inkview) -> Vec<Ipv4Addr> {
+    let Some(servers) = (unsafe { iv.GetNetDNS().as_ref() }) else {
+        return Vec::new();
+    };
+    unsafe { servers.net_int.as_slice(servers.count as usize) }
+        .iter()
+        .filter_map(parse_addr)
+        .collect()
+}
+
+/// The hardware address of the Wi-Fi adapter.
+pub fn hw_address(iv: &bindings::Inkview) -> Option<String> {
+    unsafe { string_from_ptr(iv.GetHwAddress()) }.filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn net_error_codes_round_trip() {
+        for (code, error) in NetError::CODES {
+            assert_eq!(NetError::from_code(code), Some(error));
+            assert_eq!(error.code(), code);
+        }
+        assert_eq!(NetError::from_code(0), None);
+        assert_eq!(NetError::from_code(42), None);
+        assert_eq!(NetError::from_code(-1000), Some(NetError::Other(-1000)));
+        assert_eq!(NetError::Other(-1000).code(), -1000);
+    }
+}
@@ -0,0 +1,748 @@
+//! HTTP transfers through the download manager of the firmware.
+//!
+//! The blocking `QuickDownload` functions are not wrapped, [`Session`] covers their use without
+//! blocking the event loop.
+
+use super::{check, POLL_INTERVAL};
+use crate::bindings;
+use crate::encoding::{c_string, string_from_ptr};
+use crate::error::{NetError, SessionError};
+use std::ffi::{c_char, c_int, CStr};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The state of a transfer reported by `GetSessionInfo`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SessionInfo {
+    response: i64,
+    content_type: Option<String>,
+    length: c_int,
+    progress: c_int,
+}
+
+/// The download manager calls of [`Session`] and [`Transfer`], so they can be tested without a
+/// device.
+trait Backend: Sync {
+    fn new_session(&self) -> c_int;
+    fn close_session(&self, id: c_int);
+    fn set_user_agent(&self, id: c_int, user_agent: &CStr);
+    fn set_proxy(
+        &self,
+        id: c_int,
+        host: &CStr,
+        port: c_int,
+        user: *const c_char,
+        password: *const c_char,
+    );
+    fn download_to(
+        &self,
+        id: c_int,
+        url: &CStr,
+        post: Option<&CStr>,
+        filename: &CStr,
+        timeout: c_int,
+    ) -> c_int;
+    fn status(&self, id: c_int) -> c_int;
+    fn info(&self, id: c_int) -> Option<SessionInfo>;
+    fn header(&self, id: c_int, name: &CStr) -> Option<String>;
+    fn pause(&self, id: c_int);
+    fn resume(&self, id: c_int);
+    fn abort(&self, id: c_int);
+    /// Process events until `done` returns true, calling it at least every `recheck`.
+    fn wait_until(&'static self, recheck: Duration, done: &mut dyn FnMut() -> bool);
+    /// Call `f` on the main thread after `delay`.
+    fn call_later(&'static self, delay: Duration, f: Box<dyn FnOnce() + Send>);
+}
+
+impl Backend for bindings::Inkview {
+    fn new_session(&self) -> c_int {
+        unsafe { self.NewSession() }
+    }
+
+    fn close_session(&self, id: c_int) {
+        unsafe { self.CloseSession(id) }
+    }
+
+    fn set_user_agent(&self, id: c_int, user_agent: &CStr) {
+        unsafe { self.SetUserAgent(id, user_agent.as_ptr()) }
+    }
+
+    fn set_proxy(
+        &self,
+        id: c_int,
+        host: &CStr,
+        port: c_int,
+        user: *const c_char,
+        password: *const c_char,
+    ) {
+        unsafe { self.SetProxy(id, host.as_ptr(), port, user, password) }
+    }
+
+    fn download_to(
+        &self,
+        id: c_int,
+        url: &CStr,
+        post: Option<&CStr>,
+        filename: &CStr,
+        timeout: c_int,
+    ) -> c_int {
+        unsafe {
+            self.DownloadTo(
+                id,
+                url.as_ptr(),
+                post.map_or(ptr::null(), |p| p.as_ptr()),
+                filename.as_ptr(),
+                timeout,
+            )
+        }
+    }
+
+    fn status(&self, id: c_int) -> c_int {
+        unsafe { self.GetSessionStatus(id) }
+    }
+
+    fn info(&self, id: c_int) -> Option<SessionInfo> {
+        let info = unsafe { self.GetSessionInfo(id).as_ref() }?;
+        Some(SessionInfo {
+            // c_long is only 32 bits on the device.
+            #[allow(clippy::unnecessary_cast)]
+            response: info.response as i64,
+            content_type: unsafe { string_from_ptr(info.ctype) },
+            length: info.length,
+            progress: info.progress,
+        })
+    }
+
+    fn header(&self, id: c_int, name: &CStr) -> Option<String> {
+        unsafe { string_from_ptr(self.GetHeader(id, name.as_ptr())) }
+    }
+
+    fn pause(&self, id: c_int) {
+        unsafe { self.PauseTransfer(id) }
+    }
+
+    fn resume(&self, id: c_int) {
+        unsafe { self.ResumeTransfer(id) }
+    }
+
+    fn abort(&self, id: c_int) {
+        unsafe { self.AbortTransfer(id) }
+    }
+
+    fn wait_until(&'static self, recheck: Duration, done: &mut dyn FnMut() -> bool) {
+        crate::event_loop::wait_until(self, None, Some(recheck), done);
+    }
+
+    fn call_later(&'static self, delay: Duration, f: Box<dyn FnOnce() + Send>) {
+        crate::timer::Timer::once(self, delay, f).detach();
+    }
+}
+
+/// Where the body of a response is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// Return the body in memory, it is buffered in a temporary file meanwhile.
+    Memory,
+    File(PathBuf),
+}
+
+/// A download manager session, configuring a single transfer.
+pub struct Session {
+    backend: &'static dyn Backend,
+    id: c_int,
+    timeout: Duration,
+}
+
+impl Session {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub fn new(iv: &'static bindings::Inkview) -> Result<Self, NetError> {
+        Self::with_backend(iv)
+    }
+
+    fn with_backend(backend: &'static dyn Backend) -> Result<Self, NetError> {
+        let id = backend.new_session();
+        check(id)?;
+        Ok(Self {
+            backend,
+            id,
+            timeout: Self::DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Timeout of the transfer, with a resolution of seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_user_agent(&self, user_agent: &str) -> Result<(), SessionError> {
+        let user_agent = c_string(user_agent)?;
+        self.backend.set_user_agent(self.id, &user_agent);
+        Ok(())
+    }
+
+    /// Use a proxy instead of the one configured by the user, with optional user and password.
+    pub fn set_proxy(
+        &self,
+        host: &str,
+        port: u16,
+        credentials: Option<(&str, &str)>,
+    ) -> Result<(), SessionError> {
+        let host = c_string(host)?;
+        let credentials = credentials
+            .map(|(user, password)| Ok::<_, SessionError>((c_string(user)?, c_string(password)?)))
+            .transpose()?;
+        let (user, password) = credentials
+            .as_ref()
+            .map_or((ptr::null(), ptr::null()), |(u, p)| {
+                (u.as_ptr(), p.as_ptr())
+            });
+        self.backend
+            .set_proxy(self.id, &host, port as c_int, user, password);
+        Ok(())
+    }
+
+    /// Start a GET request.
+    pub fn get(self, url: &str, destination: Destination) -> Result<Transfer, SessionError> {
+        self.start(url, None, destination)
+    }
+
+    /// Start a POST request with the form encoded `body`.
+    pub fn post(
+        self,
+        url: &str,
+        body: &str,
+        destination: Destination,
+    ) -> Result<Transfer, SessionError> {
+        self.start(url, Some(body), destination)
+    }
+
+    fn start(
+        self,
+        url: &str,
+        post: Option<&str>,
+        destination: Destination,
+    ) -> Result<Transfer, SessionError> {
+        let url = c_string(url)?;
+        let post = post.map(c_string).transpose()?;
+        let (path, in_memory) = match destination {
+            Destination::Memory => (temp_path(), true),
+            Destination::File(path) => (path, false),
+        };
+        let filename = c_string(path.to_string_lossy().into_owned())?;
+        let timeout = self.timeout.as_secs().min(c_int::MAX as u64) as c_int;
+
+        let code = self
+            .backend
+            .download_to(self.id, &url, post.as_deref(), &filename, timeout);
+        check(code)?;
+        Ok(Transfer {
+            session: self,
+            path,
+            in_memory,
+            finished: false,
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.backend.close_session(self.id);
+    }
+}
+
+fn temp_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("inkview-rs-download-{}-{n}", std::process::id()))
+}
+
+/// The state of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferStatus {
+    Connecting,
+    Transferring,
+    Done,
+    Failed(NetError),
+}
+
+/// Progress of a transfer, from `GetSessionInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Progress {
+    /// Bytes received so far.
+    pub received: u64,
+    /// Size of the body, if the server sent it.
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// Progress in percent, if the size is known.
+    pub fn percent(&self) -> Option<u8> {
+        let total = self.total.filter(|t| *t > 0)?;
+        Some((self.received.min(total) * 100 / total) as u8)
+    }
+}
+
+/// The body of a finished transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Memory(Vec<u8>),
+    File(PathBuf),
+}
+
+/// A finished transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// The HTTP status code.
+    pub status: i64,
+    pub content_type: Option<String>,
+    pub body: Body,
+}
+
+/// A running transfer, aborted when dropped before it finished.
+pub struct Transfer {
+    session: Session,
+    path: PathBuf,
+    in_memory: bool,
+    finished: bool,
+}
+
+impl Transfer {
+    pub fn status(&self) -> TransferStatus {
+        let status = self.session.backend.status(self.session.id);
+        match check(status) {
+            Err(e) => TransferStatus::Failed(e),
+            Ok(()) => match status as u32 {
+                bindings::NET_CONNECT => TransferStatus::Connecting,
+                bindings::NET_TRANSFER => TransferStatus::Transferring,
+                _ => TransferStatus::Done,
+            },
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        let info = self.session.backend.info(self.session.id);
+        info.map_or_else(Progress::default, |info| Progress {
+            received: info.progress.max(0) as u64,
+            total: (info.length > 0).then_some(info.length as u64),
+        })
+    }
+
+    /// A header of the response.
+    pub fn header(&self, name: &str) -> Result<Option<String>, SessionError> {
+        let name = c_string(name)?;
+        Ok(self.session.backend.header(self.session.id, &name))
+    }
+
+    pub fn pause(&self) {
+        self.session.backend.pause(self.session.id);
+    }
+
+    pub fn resume(&self) {
+        self.session.backend.resume(self.session.id);
+    }
+
+    /// Abort the transfer, a partially written destination file is left in place.
+    pub fn abort(mut self) {
+        self.finished = true;
+        self.session.backend.abort(self.session.id);
+    }
+
+    /// Block until the transfer finished, calling `on_progress` whenever it advanced.
+    ///
+    /// Must be called on the main thread, it processes inkview events meanwhile and may be
+    /// called from the event handler.
+    pub fn wait<F: FnMut(Progress)>(
+        mut self,
+        mut on_progress: F,
+    ) -> Result<Response, SessionError> {
+        let backend = self.session.backend;
+        let mut last = None;
+        let mut result = None;
+        backend.wait_until(POLL_INTERVAL, &mut || {
+            result = self.poll(&mut last, &mut on_progress);
+            result.is_some()
+        });
+        result.expect("waiting without a deadline only returns when done")
+    }
+
+    /// Call `on_progress` whenever the transfer advanced and `on_done` when it finished,
+    /// without blocking.
+    pub fn wait_with<P, F>(self, on_progress: P, on_done: F)
+    where
+        P: FnMut(Progress) + Send + 'static,
+        F: FnOnce(Result<Response, SessionError>) + Send + 'static,
+    {
+        poll_with(self, None, on_progress, on_done);
+    }
+
+    /// Resolves when the transfer finished, calling `on_progress` whenever it advanced.
+    ///
+    /// Requires the `async` feature.
+    #[cfg(feature = "async")]
+    pub fn wait_async<P: FnMut(Progress) + Send + 'static>(
+        self,
+        on_progress: P,
+    ) -> crate::executor::Completion<Result<Response, SessionError>> {
+        let (complete, completion) = crate::executor::completion();
+        self.wait_with(on_progress, complete);
+        completion
+    }
+
+    /// Report progress, and return the response once the transfer finished.
+    fn poll(
+        &mut self,
+        last: &mut Option<Progress>,
+        on_progress: &mut impl FnMut(Progress),
+    ) -> Option<Result<Response, SessionError>> {
+        let progress = self.progress();
+        if *last != Some(progress) {
+            *last = Some(progress);
+            on_progress(progress);
+        }
+        match self.status() {
+            TransferStatus::Connecting | TransferStatus::Transferring => None,
+            TransferStatus::Failed(e) => {
+                self.finished = true;
+                Some(Err(e.into()))
+            }
+            TransferStatus::Done => {
+                self.finished = true;
+                Some(self.response())
+            }
+        }
+    }
+
+    fn response(&self) -> Result<Response, SessionError> {
+        let info = self.session.backend.info(self.session.id);
+        let body = if self.in_memory {
+            Body::Memory(std::fs::read(&self.path).map_err(|e| io_error(&self.path, e))?)
+        } else {
+            Body::File(self.path.clone())
+        };
+        Ok(Response {
+            status: info.as_ref().map_or(0, |info| info.response),
+            content_type: info.and_then(|info| info.content_type),
+            body,
+        })
+    }
+}
+
+fn poll_with<P, F>(
+    mut transfer: Transfer,
+    mut last: Option<Progress>,
+    mut on_progress: P,
+    on_done: F,
+) where
+    P: FnMut(Progress) + Send + 'static,
+    F: FnOnce(Result<Response, SessionError>) + Send + 'static,
+{
+    if let Some(result) = transfer.poll(&mut last, &mut on_progress) {
+        on_done(result);
+        return;
+    }
+    let backend = transfer.session.backend;
+    backend.call_later(
+        POLL_INTERVAL,
+        Box::new(move || poll_with(transfer, last, on_progress, on_done)),
+    );
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.session.backend.abort(self.session.id);
+        }
+        if self.in_memory {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> SessionError {
+    SessionError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const ID: c_int = 7;
+
+    /// Replays `steps` of (status, info), one per `GetSessionStatus` call, and writes `body` to
+    /// the destination when the download starts.
+    #[derive(Default)]
+    struct FakeBackend {
+        steps: Vec<(c_int, Option<SessionInfo>)>,
+        download_code: c_int,
+        body: Vec<u8>,
+        state: Mutex<FakeState>,
+    }
+
+    #[derive(Default)]
+    struct FakeState {
+        step: usize,
+        downloads: Vec<(String, Option<String>, PathBuf, c_int)>,
+        aborted: Vec<c_int>,
+        closed: Vec<c_int>,
+        later: Vec<Box<dyn FnOnce() + Send>>,
+    }
+
+    impl Backend for FakeBackend {
+        fn new_session(&self) -> c_int {
+            ID
+        }
+
+        fn close_session(&self, id: c_int) {
+            self.state.lock().unwrap().closed.push(id);
+        }
+
+        fn set_user_agent(&self, _id: c_int, _user_agent: &CStr) {}
+
+        fn set_proxy(
+            &self,
+            _id: c_int,
+            _host: &CStr,
+            _port: c_int,
+            _user: *const c_char,
+            _password: *const c_char,
+        ) {
+        }
+
+        fn download_to(
+            &self,
+            _id: c_int,
+            url: &CStr,
+            post: Option<&CStr>,
+            filename: &CStr,
+            timeout: c_int,
+        ) -> c_int {
+            let to_string = |s: &CStr| s.to_str().unwrap().to_string();
+            let path = PathBuf::from(to_string(filename));
+            if self.download_code >= 0 {
+                std::fs::write(&path, &self.body).unwrap();
+            }
+            let download = (to_string(url), post.map(to_string), path, timeout);
+            self.state.lock().unwrap().downloads.push(download);
+            self.download_code
+        }
+
+        fn status(&self, _id: c_int) -> c_int {
+            let mut state = self.state.lock().unwrap();
+            let status = self.steps[state.step].0;
+            state.step = (state.step + 1).min(self.steps.len() - 1);
+            status
+        }
+
+        fn info(&self, _id: c_int) -> Option<SessionInfo> {
+            let step = self.state.lock().unwrap().step;
+            self.steps[step].1.clone()
+        }
+
+        fn header(&self, _id: c_int, _name: &CStr) -> Option<String> {
+            None
+        }
+
+        fn pause(&self, _id: c_int) {}
+
+        fn resume(&self, _id: c_int) {}
+
+        fn abort(&self, id: c_int) {
+            self.state.lock().unwrap().aborted.push(id);
+        }
+
+        fn wait_until(&'static self, _recheck: Duration, done: &mut dyn FnMut() -> bool) {
+            while !done() {}
+        }
+
+        fn call_later(&'static self, _delay: Duration, f: Box<dyn FnOnce() + Send>) {
+            self.state.lock().unwrap().later.push(f);
+        }
+    }
+
+    impl FakeBackend {
+        fn run_later(&self) -> bool {
+            let later = std::mem::take(&mut self.state.lock().unwrap().later);
+            let ran = !later.is_empty();
+            later.into_iter().for_each(|f| f());
+            ran
+        }
+    }
+
+    fn backend(steps: Vec<(c_int, Option<SessionInfo>)>) -> &'static FakeBackend {
+        Box::leak(Box::new(FakeBackend {
+            steps,
+            body: b"hello".to_vec(),
+            ..Default::default()
+        }))
+    }
+
+    fn info(progress: c_int, length: c_int) -> Option<SessionInfo> {
+        Some(SessionInfo {
+            progress,
+            length,
+            ..Default::default()
+        })
+    }
+
+    fn done(response: i64) -> (c_int, Option<SessionInfo>) {
+        let info = SessionInfo {
+            response,
+            content_type: Some("text/plain".to_string()),
+            length: 5,
+            progress: 5,
+        };
+        (bindings::NET_OK as c_int, Some(info))
+    }
+
+    fn get(backend: &'static FakeBackend, destination: Destination) -> Transfer {
+        Session::with_backend(backend)
+            .unwrap()
+            .get("http://example.com/", destination)
+            .unwrap()
+    }
+
+    #[test]
+    fn status_maps_session_codes() {
+        let connect = (bindings::NET_CONNECT as c_int, None);
+        let transfer = (bindings::NET_TRANSFER as c_int, None);
+        let transfer = get(
+            backend(vec![connect, transfer, done(200)]),
+            Destination::Memory,
+        );
+        assert_eq!(transfer.status(), TransferStatus::Connecting);
+        assert_eq!(transfer.status(), TransferStatus::Transferring);
+        assert_eq!(transfer.status(), TransferStatus::Done);
+
+        let timeout = (bindings::NET_ETIMEOUT, None);
+        let transfer = get(backend(vec![timeout]), Destination::Memory);
+        assert_eq!(transfer.status(), TransferStatus::Failed(NetError::Timeout));
+    }
+
+    #[test]
+    fn progress_maps_session_info() {
+        let progress = |info| get(backend(vec![(0, info)]), Destination::Memory).progress();
+        assert_eq!(progress(None), Progress::default());
+        assert_eq!(
+            progress(info(10, 0)),
+            Progress {
+                received: 10,
+                total: None
+            }
+        );
+        assert_eq!(
+            progress(info(-1, 200)),
+            Progress {
+                received: 0,
+                total: Some(200)
+            }
+        );
+    }
+
+    #[test]
+    fn wait_reports_progress_changes_and_reads_the_body() {
+        let transferring = bindings::NET_TRANSFER as c_int;
+        let backend = backend(vec![
+            (bindings::NET_CONNECT as c_int, info(0, 0)),
+            (transferring, info(2, 5)),
+            (transferring, info(2, 5)),
+            done(200),
+        ]);
+        let mut reported = Vec::new();
+        let response = get(backend, Destination::Memory)
+            .wait(|progress| reported.push(progress.percent()))
+            .unwrap();
+
+        assert_eq!(reported, [None, Some(40), Some(100)]);
+        assert_eq!(
+            response,
+            Response {
+                status: 200,
+                content_type: Some("text/plain".to_string()),
+                body: Body::Memory(b"hello".to_vec()),
+            }
+        );
+
+        let state = backend.state.lock().unwrap();
+        let (url, post, path, timeout) = &state.downloads[0];
+        assert_eq!(url, "http://example.com/");
+        assert_eq!(*post, None);
+        assert_eq!(*timeout, 60);
+        assert!(!path.exists(), "the buffer file is removed");
+        assert!(state.aborted.is_empty());
+        assert_eq!(state.closed, [ID]);
+    }
+
+    #[test]
+    fn wait_with_polls_until_done() {
+        let transferring = (bindings::NET_TRANSFER as c_int, info(0, 5));
+        let backend = backend(vec![transferring, done(404)]);
+        let path = std::env::temp_dir().join(format!("inkview-rs-test-{}", std::process::id()));
+        let result = Arc::new(Mutex::new(None));
+        get(backend, Destination::File(path.clone())).wait_with(|_| {}, {
+            let result = result.clone();
+            move |r| *result.lock().unwrap() = Some(r)
+        });
+
+        while backend.run_later() {}
+        let response = result.lock().unwrap().take().unwrap().unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, Body::File(path.clone()));
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn errors_map_to_net_errors() {
+        let failing = Box::leak(Box::new(FakeBackend {
+            steps: vec![(0, None)],
+            download_code: bindings::NET_EURL,
+            ..Default::default()
+        }));
+        let result =
+            Session::with_backend(failing)
+                .unwrap()
+                .post("nowhere", "a=1", Destination::Memory);
+        assert!(matches!(result, Err(SessionError::Net(NetError::Url))));
+        assert_eq!(
+            failing.state.lock().unwrap().downloads[0].1.as_deref(),
+            Some("a=1")
+        );
+
+        let backend = backend(vec![(bindings::NET_ERESOLVE, None)]);
+        let result = get(backend, Destination::Memory).wait(|_| {});
+        assert!(matches!(result, Err(SessionError::Net(NetError::Resolve))));
+        assert!(backend.state.lock().unwrap().aborted.is_empty());
+    }
+
+    #[test]
+    fn dropping_an_unfinished_transfer_aborts_it() {
+        let backend = backend(vec![(bindings::NET_TRANSFER as c_int, None)]);
+        let transfer = get(backend, Destination::Memory);
+        let path = backend.state.lock().unwrap().downloads[0].2.clone();
+        drop(transfer);
+
+        let state = backend.state.lock().unwrap();
+        assert_eq!(state.aborted, [ID]);
+        assert_eq!(state.closed, [ID]);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn progress_percent() {
+        let progress = |received, total| Progress { received, total };
+        assert_eq!(progress(0, None).percent(), None);
+        assert_eq!(progress(10, Some(0)).percent(), None);
+        assert_eq!(progress(0, Some(200)).percent(), Some(0));
+        assert_eq!(progress(199, Some(200)).percent(), Some(99));
+        assert_eq!(progress(200, Some(200)).percent(), Some(100));
+        // More than announced by the server.
+        assert_eq!(progress(300, Some(200)).percent(), Some(100));
+    }
+}
@@ -0,0 +1,230 @@
+//! Battery and power status, sleep and power-off control.
+//!
+//! Inkview sends no events for battery or charging changes, [`watch`] polls the status
+//! instead. Only USB storage mode is reported through [`crate::Event::UsbStoreIn`] and
+//! [`crate::Event::UsbStoreOut`].
+
+use crate::bindings;
+use crate::encoding::c_string;
+use crate::timer::Timer;
+use crate::Error;
+use std::ffi::c_int;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A snapshot of the power status of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerStatus {
+    /// Battery charge in percent.
+    pub battery_percent: u8,
+    pub charging: bool,
+    pub usb_connected: bool,
+    /// Device temperature in degrees Celsius.
+    pub temperature: i32,
+}
+
+impl PowerStatus {
+    /// Battery charge below which the battery is considered low.
+    pub const LOW_BATTERY_PERCENT: u8 = 20;
+
+    pub fn read(iv: &bindings::Inkview) -> Self {
+        unsafe {
+            Self {
+                battery_percent: battery_percent(iv),
+                charging: iv.IsCharging() != 0,
+                usb_connected: iv.IsUSBconnected() != 0,
+                temperature: iv.GetTemperature(),
+            }
+        }
+    }
+
+    /// Whether the battery is low and the device is not charging.
+    pub fn is_low(&self) -> bool {
+        !self.charging && self.battery_percent < Self::LOW_BATTERY_PERCENT
+    }
+}
+
+/// Battery charge in percent.
+///
+/// Uses `device_battery_percent` where available, `GetBatteryPower` otherwise.
+pub fn battery_percent(iv: &bindings::Inkview) -> u8 {
+    #[cfg(not(feature = "sdk-5-19"))]
+    if iv.device_battery_percent.is_ok() {
+        return unsafe { iv.device_battery_percent() }.clamp(0, 100) as u8;
+    }
+    unsafe { iv.GetBatteryPower() }.clamp(0, 100) as u8
+}
+
+/// Whether there is enough power to safely run a long job, like a large download.
+///
+/// Uses the same check as the firmware before installing an update.
+pub fn can_run_long_job(iv: &bindings::Inkview) -> bool {
+    // Returns an int in older SDKs.
+    #[cfg(any(feature = "sdk-5-19", feature = "sdk-6-5"))]
+    return unsafe { iv.HavePowerForSoftwareUpdate() } != 0;
+    #[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+    return unsafe { iv.HavePowerForSoftwareUpdate() };
+}
+
+/// Call `on_change` with the new status whenever a field of [`PowerStatus`] changed.
+///
+/// The status is checked every `interval`, without waking up the device from sleep. The
+/// returned timer stops watching when dropped. USB storage mode is not covered, handle
+/// [`crate::Event::UsbStoreIn`] for it.
+pub fn watch<F: FnMut(PowerStatus) + Send + 'static>(
+    iv: &'static bindings::Inkview,
+    interval: Duration,
+    mut on_change: F,
+) -> Timer {
+    let mut last = PowerStatus::read(iv);
+    Timer::repeating_weak(iv, interval, move || {
+        let status = PowerStatus::read(iv);
+        if status != last {
+            last = status;
+            on_change(status);
+        }
+    })
+}
+
+/// Keeps the device from going to sleep while alive, e.g. during a download.
+///
+/// Uses the sleep preventors of the firmware where available. Otherwise sleep is banned through
+/// `BanSleep`, renewed by a timer shared by all guards. Sleep is allowed again when the last guard
+/// is dropped, also while unwinding from a panic.
+#[must_use = "sleep is allowed again when the guard is dropped"]
+pub struct SleepGuard {
+    iv: &'static bindings::Inkview,
+    inner: SleepGuardInner,
+}
+
+enum SleepGuardInner {
+    #[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+    Preventor {
+        // Boxed, the firmware keeps the pointer until the preventor is stopped.
+        preventor: Box<bindings::iv_sleep_preventor_t>,
+        _name: std::ffi::CString,
+    },
+    Ban,
+}
+
+/// The `BanSleep` state shared by all guards without a sleep preventor.
+struct SleepBan {
+    guards: usize,
+    _renew: Timer,
+}
+
+static SLEEP_BAN: Mutex<Option<SleepBan>> = Mutex::new(None);
+
+impl SleepGuard {
+    /// Duration of a single `BanSleep` call, renewed after half of it.
+    const BAN_DURATION: Duration = Duration::from_secs(60);
+
+    /// Prevent sleep until the guard is dropped, `name` identifies the guard in firmware logs.
+    pub fn new(iv: &'static bindings::Inkview, name: &str) -> Result<Self, Error> {
+        let name = c_string(name)?;
+
+        #[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+        if iv.iv_sleep_preventor_start.is_ok() && iv.iv_sleep_preventor_stop.is_ok() {
+            let mut preventor = Box::new(bindings::iv_sleep_preventor_t {
+                name: name.as_ptr(),
+                forbidden: false,
+            });
+            unsafe {
+                iv.iv_sleep_preventor_start(&mut *preventor);
+            }
+            return Ok(Self {
+                iv,
+                inner: SleepGuardInner::Preventor {
+                    preventor,
+                    _name: name,
+                },
+            });
+        }
+
+        drop(name);
+        let mut ban = SLEEP_BAN.lock().unwrap();
+        match ban.as_mut() {
+            Some(ban) => ban.guards += 1,
+            None => {
+                let seconds = Self::BAN_DURATION.as_secs() as c_int;
+                unsafe {
+                    iv.BanSleep(seconds);
+                }
+                let renew = Timer::repeating(iv, Self::BAN_DURATION / 2, move || unsafe {
+                    iv.BanSleep(seconds);
+                });
+                *ban = Some(SleepBan {
+                    guards: 1,
+                    _renew: renew,
+                });
+            }
+        }
+        Ok(Self {
+            iv,
+            inner: SleepGuardInner::Ban,
+        })
+    }
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        match &mut self.inner {
+            #[cfg(not(any(feature = "sdk-5-19", feature = "sdk-6-5")))]
+            SleepGuardInner::Preventor { preventor, .. } => unsafe {
+                self.iv.iv_sleep_preventor_stop(&mut **preventor);
+            },
+            SleepGuardInner::Ban => {
+                let last = {
+                    let mut ban = SLEEP_BAN.lock().unwrap();
+                    match ban.as_mut() {
+                        Some(b) if b.guards > 1 => {
+                            b.guards -= 1;
+                            None
+                        }
+                        _ => ban.take(),
+                    }
+                };
+                if let Some(last) = last {
+                    // Cancels the renew timer.
+                    drop(last);
+                    unsafe {
+                        self.iv.BanSleep(0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Enable or disable the automatic power-off after inactivity.
+pub fn set_auto_power_off(iv: &bindings::Inkview, enabled: bool) {
+    // Takes an int in older SDKs.
+    #[cfg(feature = "sdk-5-19")]
+    let enabled = enabled as c_int;
+    unsafe {
+        iv.SetAutoPowerOff(enabled);
+    }
+}
+
+/// Enable or disable locking the keys when the device goes to sleep.
+#[cfg(not(feature = "sdk-5-19"))]
+pub fn set_auto_keylock(iv: &bindings::Inkview, enabled: bool) {
+    unsafe {
+        iv.SetAutoKeylock(enabled);
+    }
+}
+
+/// Put the device to sleep for `duration`, `deep` selects deep sleep.
+pub fn go_sleep(iv: &bindings::Inkview, duration: Duration, deep: bool) {
+    let ms = duration.as_millis().min(c_int::MAX as u128) as c_int;
+    unsafe {
+        iv.GoSleep(ms, deep as c_int);
+    }
+}
+
+/// Postpone the timed power-off, e.g. in response to [`crate::Event::PostponeTimedPowerOff`].
+pub fn postpone_timed_power_off(iv: &bindings::Inkview) {
+    unsafe {
+        iv.PostponeTimedPoweroff();
+    }
+}
@@ -0,0 +1,235 @@
+//! Native progress bars and busy indicators, closed when their guard is dropped.
+
+use crate::bindings;
+use crate::callback::Slot;
+use crate::dialogs::Icon;
+use crate::encoding::c_string;
+use crate::main_thread::MainThreadHandle;
+use crate::Error;
+use std::ffi::{c_int, CString};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+type CancelCallback = Box<dyn FnOnce() + Send>;
+
+static CANCEL_CALLBACK: Slot<CancelCallback> = Slot::new();
+
+/// Incremented for every opened progress dialog, so stale updates can be discarded.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn progress_handler(_button: c_int) {
+    // The firmware closed the dialog, discard further updates.
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    if let Some(callback) = CANCEL_CALLBACK.take() {
+        callback();
+    }
+}
+
+/// A native progress dialog, closed on drop.
+///
+/// Must be created and dropped on the main thread, use [`ProgressDialog::updater`] to report
+/// progress from other threads.
+#[must_use]
+pub struct ProgressDialog {
+    iv: &'static bindings::Inkview,
+    generation: u64,
+}
+
+impl ProgressDialog {
+    /// Open a progress dialog, replacing a progress dialog that is currently open.
+    pub fn open(
+        iv: &'static bindings::Inkview,
+        icon: Icon,
+        title: impl Into<String>,
+        text: impl Into<String>,
+    ) -> Result<Self, Error> {
+        Self::open_inner(iv, icon, title.into(), text.into(), None)
+    }
+
+    /// Open a progress dialog that can be cancelled by the user.
+    ///
+    /// `on_cancel` is called on the main thread when the user cancels the dialog. The dialog
+    /// is closed by the firmware in that case.
+    pub fn open_cancellable<F: FnOnce() + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        icon: Icon,
+        title: impl Into<String>,
+        text: impl Into<String>,
+        on_cancel: F,
+    ) -> Result<Self, Error> {
+        Self::open_inner(
+            iv,
+            icon,
+            title.into(),
+            text.into(),
+            Some(Box::new(on_cancel)),
+        )
+    }
+
+    fn open_inner(
+        iv: &'static bindings::Inkview,
+        icon: Icon,
+        title: String,
+        text: String,
+        on_cancel: Option<CancelCallback>,
+    ) -> Result<Self, Error> {
+        let title = c_string(title)?;
+        let text = c_string(text)?;
+        let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        let handler = on_cancel.is_some().then_some(progress_handler as _);
+        // The callback of a replaced dialog is dropped, it wasn't cancelled.
+        match on_cancel {
+            Some(on_cancel) => drop(CANCEL_CALLBACK.replace(on_cancel)),
+            None => drop(CANCEL_CALLBACK.take()),
+        }
+
+        unsafe {
+            iv.OpenProgressbar(icon as c_int, title.as_ptr(), text.as_ptr(), 0, handler);
+        }
+        Ok(Self { iv, generation })
+    }
+
+    /// Update the text and the progress in percent.
+    pub fn update(&self, text: impl Into<String>, percent: u8) -> Result<(), Error> {
+        update(self.iv, self.generation, c_string(text.into())?, percent);
+        Ok(())
+    }
+
+    /// A handle to update the progress from any thread.
+    pub fn updater(&self) -> ProgressUpdater {
+        ProgressUpdater {
+            handle: MainThreadHandle::new(self.iv),
+            iv: self.iv,
+            generation: self.generation,
+        }
+    }
+}
+
+impl Drop for ProgressDialog {
+    fn drop(&mut self) {
+        // Only close the dialog if it wasn't replaced by a newer one.
+        if GENERATION
+            .compare_exchange(
+                self.generation,
+                self.generation + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+        {
+            drop(CANCEL_CALLBACK.take());
+            unsafe {
+                self.iv.CloseProgressbar();
+            }
+        }
+    }
+}
+
+fn update(iv: &bindings::Inkview, generation: u64, text: CString, percent: u8) {
+    if GENERATION.load(Ordering::SeqCst) != generation {
+        return;
+    }
+    unsafe {
+        iv.UpdateProgressbar(text.as_ptr(), percent.min(100) as c_int);
+    }
+}
+
+/// Updates a [`ProgressDialog`] from any thread through [`crate::dispatch`].
+///
+/// Updates arriving after the dialog was closed are ignored.
+#[derive(Clone)]
+pub struct ProgressUpdater {
+    handle: MainThreadHandle,
+    iv: &'static bindings::Inkview,
+    generation: u64,
+}
+
+impl ProgressUpdater {
+    /// Update the text and the progress in percent.
+    pub fn update(&self, text: impl Into<String>, percent: u8) -> Result<(), Error> {
+        let iv = self.iv;
+        let generation = self.generation;
+        let text = c_string(text.into())?;
+        self.handle
+            .dispatch(move || update(iv, generation, text, percent));
+        Ok(())
+    }
+}
+
+/// The native busy indicator, hidden when the last guard is dropped.
+#[must_use]
+pub struct Hourglass {
+    iv: &'static bindings::Inkview,
+}
+
+/// Number of alive [`Hourglass`] guards.
+static HOURGLASSES: Mutex<usize> = Mutex::new(0);
+
+impl Hourglass {
+    /// Show the busy indicator in the center of the screen.
+    pub fn show(iv: &'static bindings::Inkview) -> Self {
+        *HOURGLASSES.lock().unwrap() += 1;
+        unsafe {
+            iv.ShowHourglass();
+        }
+        Self { iv }
+    }
+
+    /// Show the busy indicator at the given position, moving it if it is already shown.
+    pub fn show_at(iv: &'static bindings::Inkview, x: i32, y: i32) -> Self {
+        *HOURGLASSES.lock().unwrap() += 1;
+        unsafe {
+            iv.ShowHourglassAt(x, y);
+        }
+        Self { iv }
+    }
+}
+
+impl Drop for Hourglass {
+    fn drop(&mut self) {
+        let mut count = HOURGLASSES.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            unsafe {
+                self.iv.HideHourglass();
+            }
+        }
+    }
+}
+
+/// The progress indicator in the panel, stopped on drop.
+#[must_use]
+pub struct PanelProgress {
+    iv: &'static bindings::Inkview,
+    timeout: Duration,
+}
+
+impl PanelProgress {
+    /// Start the panel progress indicator.
+    ///
+    /// The firmware stops the indicator by itself if it isn't updated within `timeout`.
+    pub fn start(iv: &'static bindings::Inkview, percent: u8, timeout: Duration) -> Self {
+        let progress = Self { iv, timeout };
+        progress.update(percent);
+        progress
+    }
+
+    /// Update the progress in percent.
+    pub fn update(&self, percent: u8) {
+        unsafe {
+            self.iv.StartPanelProgress(
+                percent.min(100) as c_int,
+                self.timeout.as_millis().min(c_int::MAX as u128) as c_int,
+            );
+        }
+    }
+}
+
+impl Drop for PanelProgress {
+    fn drop(&mut self) {
+        unsafe {
+            self.iv.StopPanelProgress();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::bindings::APPLICATION_ATTRIBUTE_APPLICATION_READER;
+use crate::bindings::{self, APPLICATION_ATTRIBUTE_APPLICATION_READER};
 use crate::error;
 use crate::{bindings::icanvas_s, bindings::Inkview};
 use core::ffi::c_int;
@@ -83,7 +83,6 @@ impl PixelFormat for RGB24 {
 pub struct Screen<'a, P: 'static> {
     iv: &'a Inkview,
 
-    #[allow(unused)]
     fb: &'a mut icanvas_s,
     width: usize,
     height: usize,
@@ -322,6 +321,16 @@ impl<'a, P> Screen<'a, P> {
     /// Set the current screen orientation
     pub fn set_orientation(&mut self, orientation: ScreenOrientation) {
         unsafe { self.iv.SetOrientation(orientation.to_iv()) }
+        self.update_geometry();
+    }
+
+    /// Re-read the framebuffer geometry, after the orientation was changed elsewhere, e.g. by
+    /// [`crate::gsensor::AutoRotation`].
+    pub fn update_geometry(&mut self) {
+        self.width = self.fb.width as usize;
+        self.height = self.fb.height as usize;
+        self.stride = self.fb.scanline as usize;
+        self.buf = self.fb.addr;
     }
 
     /// DPI of screen
@@ -335,8 +344,97 @@ impl<'a, P> Screen<'a, P> {
     }
 }
 
+/// A rectangle in screen coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl From<bindings::irect> for Rect {
+    fn from(r: bindings::irect) -> Self {
+        Self {
+            x: r.x,
+            y: r.y,
+            w: r.w,
+            h: r.h,
+        }
+    }
+}
+
+impl From<Rect> for bindings::irect {
+    fn from(r: Rect) -> Self {
+        Self {
+            x: r.x,
+            y: r.y,
+            w: r.w,
+            h: r.h,
+            flags: 0,
+        }
+    }
+}
+
+/// An 8-bit grayscale bitmap in the `ibitmap` layout of inkview, owned by Rust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    /// The `ibitmap` header followed by the pixels, as `u64` for the alignment of the header.
+    data: Vec<u64>,
+}
+
+impl Bitmap {
+    const HEADER_LEN: usize = std::mem::size_of::<bindings::ibitmap>();
+
+    /// Create a bitmap from `pixels`, row by row.
+    ///
+    /// Returns `None` if the number of pixels does not match the size.
+    pub fn from_gray8(width: u16, height: u16, pixels: &[BB8]) -> Option<Self> {
+        let len = width as usize * height as usize;
+        if pixels.len() != len {
+            return None;
+        }
+        let mut data = vec![0u64; (Self::HEADER_LEN + len).div_ceil(8)];
+        let header = bindings::ibitmap {
+            width,
+            height,
+            depth: 8,
+            scanline: width,
+            data: bindings::__IncompleteArrayField::new(),
+        };
+        unsafe {
+            let ptr = data.as_mut_ptr() as *mut u8;
+            (ptr as *mut bindings::ibitmap).write(header);
+            for (i, pixel) in pixels.iter().enumerate() {
+                ptr.add(Self::HEADER_LEN + i).write(pixel.0);
+            }
+        }
+        Some(Self { data })
+    }
+
+    pub fn width(&self) -> u16 {
+        unsafe { (*self.as_ptr()).width }
+    }
+
+    pub fn height(&self) -> u16 {
+        unsafe { (*self.as_ptr()).height }
+    }
+
+    /// The bitmap for passing it to inkview, valid while `self` is alive.
+    pub(crate) fn as_ptr(&self) -> *const bindings::ibitmap {
+        self.data.as_ptr() as *const bindings::ibitmap
+    }
+}
+
 #[derive(
-    Debug, Clone, Copy, PartialEq, PartialOrd, num_derive::FromPrimitive, num_derive::ToPrimitive,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    num_derive::FromPrimitive,
+    num_derive::ToPrimitive,
 )]
 pub enum ScreenOrientation {
     Portrait0Deg = 0,
@@ -390,16 +488,21 @@ impl TryFrom<ScreenOrientation> for i32 {
 
 impl ScreenOrientation {
     fn from_iv(raw: c_int) -> Self {
+        Self::try_from_iv(raw)
+            .unwrap_or_else(|| panic!("ScreenOrientation from inkview enum invalid num: {raw}"))
+    }
+
+    pub(crate) fn try_from_iv(raw: c_int) -> Option<Self> {
         match raw {
-            0 => Self::Portrait0Deg,
-            1 => Self::Landscape270Deg,
-            2 => Self::Landscape90Deg,
-            3 => Self::Portrait180Deg,
-            raw => panic!("ScreenOrientation from inkview enum invalid num: {raw}"),
+            0 => Some(Self::Portrait0Deg),
+            1 => Some(Self::Landscape270Deg),
+            2 => Some(Self::Landscape90Deg),
+            3 => Some(Self::Portrait180Deg),
+            _ => None,
         }
     }
 
-    fn to_iv(self) -> c_int {
+    pub(crate) fn to_iv(self) -> c_int {
         match self {
             ScreenOrientation::Portrait0Deg => 0,
             ScreenOrientation::Landscape90Deg => 2,
@@ -0,0 +1,482 @@
+//! Storage for credentials like API tokens.
+//!
+//! Secrets are stored through `ReadSecret`/`WriteSecret` of the firmware. If the loaded
+//! `libinkview.so` lacks these functions, they are stored in a file encrypted with
+//! ChaCha20-Poly1305, using a key derived from the device key and serial number. This keeps the
+//! secrets from being readable as plain files, but anyone with access to the device can derive
+//! the key.
+//!
+//! Requires the `secrets` feature.
+
+use crate::bindings;
+use crate::config::Config;
+use crate::error::{ConfigError, SecretError};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+
+/// The secret storage of the firmware, implemented by [`Config`].
+trait NativeSecrets {
+    fn get_secret(&self, key: &str) -> Result<Option<String>, ConfigError>;
+    fn set_secret(&mut self, key: &str, value: &str) -> Result<(), ConfigError>;
+    fn remove(&mut self, key: &str) -> Result<(), ConfigError>;
+    fn save(&mut self) -> Result<(), ConfigError>;
+}
+
+impl NativeSecrets for Config {
+    fn get_secret(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        Config::get_secret(self, key)
+    }
+
+    fn set_secret(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        Config::set_secret(self, key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), ConfigError> {
+        Config::remove(self, key)
+    }
+
+    fn save(&mut self) -> Result<(), ConfigError> {
+        Config::save(self)
+    }
+}
+
+/// Where the secrets of the running app are stored.
+#[derive(Debug, PartialEq)]
+enum Location {
+    Native(PathBuf),
+    File(PathBuf),
+}
+
+impl Location {
+    /// Native secrets are used if the firmware has both `ReadSecret` and `WriteSecret`.
+    fn of_app(dir: &Path, read_secret: bool, write_secret: bool) -> Self {
+        if read_secret && write_secret {
+            Self::Native(dir.join("secrets.cfg"))
+        } else {
+            Self::File(dir.join("secrets.bin"))
+        }
+    }
+}
+
+enum Backend {
+    Native(Box<dyn NativeSecrets>),
+    File {
+        path: PathBuf,
+        cipher: Box<ChaCha20Poly1305>,
+        entries: BTreeMap<String, Vec<u8>>,
+    },
+    Memory(BTreeMap<String, Vec<u8>>),
+}
+
+/// A store of named secrets.
+///
+/// Must only be used on the main thread.
+pub struct Secrets {
+    backend: Backend,
+}
+
+impl Secrets {
+    /// Header of the encrypted secrets file, followed by the nonce and the ciphertext.
+    const FILE_MAGIC: &'static [u8] = b"IVRS-SECRETS-1\n";
+    const NONCE_LEN: usize = 12;
+
+    /// Open the secrets of the running app, stored inside [`crate::app::config_dir`].
+    pub fn open(iv: &'static bindings::Inkview) -> Result<Self, SecretError> {
+        let dir = crate::app::config_dir();
+        match Location::of_app(&dir, iv.ReadSecret.is_ok(), iv.WriteSecret.is_ok()) {
+            Location::Native(path) => Self::open_native(iv, path),
+            Location::File(path) => Self::open_file(iv, path),
+        }
+    }
+
+    /// Open secrets stored through `ReadSecret`/`WriteSecret` in the config file at `path`.
+    pub fn open_native(
+        iv: &'static bindings::Inkview,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, SecretError> {
+        let path = path.as_ref();
+        create_parent(path)?;
+        Ok(Self {
+            backend: Backend::Native(Box::new(Config::open(iv, path)?)),
+        })
+    }
+
+    /// Open secrets stored in the encrypted file at `path`.
+    pub fn open_file(
+        iv: &'static bindings::Inkview,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, SecretError> {
+        let path = path.into();
+        let cipher = Box::new(ChaCha20Poly1305::new(&device_key(iv).into()));
+        let entries = match std::fs::read(&path) {
+            Ok(data) => decrypt(&cipher, &data).ok_or_else(|| SecretError::Corrupted {
+                path: path.display().to_string(),
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(io_error(&path, e)),
+        };
+        Ok(Self {
+            backend: Backend::File {
+                path,
+                cipher,
+                entries,
+            },
+        })
+    }
+
+    /// Secrets that are only kept in memory, e.g. for tests.
+    pub fn in_memory() -> Self {
+        Self {
+            backend: Backend::Memory(BTreeMap::new()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Vec<u8>>, SecretError> {
+        match &self.backend {
+            Backend::Native(config) => Ok(config.get_secret(name)?.and_then(|v| decode_hex(&v))),
+            Backend::File { entries, .. } | Backend::Memory(entries) => {
+                Ok(entries.get(name).cloned())
+            }
+        }
+    }
+
+    /// Get a secret stored as UTF-8 string.
+    pub fn get_string(&self, name: &str) -> Result<Option<String>, SecretError> {
+        Ok(self.get(name)?.and_then(|v| String::from_utf8(v).ok()))
+    }
+
+    /// Store a secret, replacing a secret with the same name.
+    pub fn set(&mut self, name: &str, value: &[u8]) -> Result<(), SecretError> {
+        match &mut self.backend {
+            Backend::Native(config) => {
+                // Native secrets are C strings, so arbitrary bytes are stored hex encoded.
+                config.set_secret(name, &encode_hex(value))?;
+                config.save()?;
+                Ok(())
+            }
+            Backend::File { entries, .. } => {
+                entries.insert(name.to_string(), value.to_vec());
+                self.write_file()
+            }
+            Backend::Memory(entries) => {
+                entries.insert(name.to_string(), value.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_string(&mut self, name: &str, value: &str) -> Result<(), SecretError> {
+        self.set(name, value.as_bytes())
+    }
+
+    /// Remove a secret, returns whether it existed.
+    pub fn remove(&mut self, name: &str) -> Result<bool, SecretError> {
+        match &mut self.backend {
+            Backend::Native(config) => {
+                let existed = config.get_secret(name)?.is_some();
+                if existed {
+                    config.remove(name)?;
+                    config.save()?;
+                }
+                Ok(existed)
+            }
+            Backend::File { entries, .. } => {
+                let existed = entries.remove(name).is_some();
+                if existed {
+                    self.write_file()?;
+                }
+                Ok(existed)
+            }
+            Backend::Memory(entries) => Ok(entries.remove(name).is_some()),
+        }
+    }
+
+    fn write_file(&self) -> Result<(), SecretError> {
+        let Backend::File {
+            path,
+            cipher,
+            entries,
+        } = &self.backend
+        else {
+            return Ok(());
+        };
+        let data = encrypt(cipher, entries).ok_or_else(|| SecretError::Corrupted {
+            path: path.display().to_string(),
+        })?;
+        create_parent(path)?;
+        // Write to a temporary file first, so a kill while saving does not lose all secrets.
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data).map_err(|e| io_error(&tmp, e))?;
+        std::fs::rename(&tmp, path).map_err(|e| io_error(path, e))
+    }
+}
+
+fn create_parent(path: &Path) -> Result<(), SecretError> {
+    match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e)),
+        None => Ok(()),
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> SecretError {
+    SecretError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    }
+}
+
+/// Derive the file encryption key from the device identity and the app name.
+fn device_key(iv: &bindings::Inkview) -> [u8; 32] {
+    let read = |s: *mut std::ffi::c_char| {
+        if s.is_null() {
+            Vec::new()
+        } else {
+            unsafe { CStr::from_ptr(s) }.to_bytes().to_vec()
+        }
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(b"inkview-rs secrets\0");
+    if iv.GetDeviceKey.is_ok() {
+        hasher.update(read(unsafe { iv.GetDeviceKey() }));
+    }
+    hasher.update(b"\0");
+    if iv.GetSerialNumber.is_ok() {
+        hasher.update(read(unsafe { iv.GetSerialNumber() }));
+    }
+    hasher.update(b"\0");
+    hasher.update(crate::app::app_name().as_bytes());
+    hasher.finalize().into()
+}
+
+fn encrypt(cipher: &ChaCha20Poly1305, entries: &BTreeMap<String, Vec<u8>>) -> Option<Vec<u8>> {
+    let mut plain = Vec::new();
+    for (name, value) in entries {
+        for field in [name.as_bytes(), value.as_slice()] {
+            plain.extend_from_slice(&u32::try_from(field.len()).ok()?.to_le_bytes());
+            plain.extend_from_slice(field);
+        }
+    }
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, plain.as_slice()).ok()?;
+
+    let mut data = Secrets::FILE_MAGIC.to_vec();
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Some(data)
+}
+
+fn decrypt(cipher: &ChaCha20Poly1305, data: &[u8]) -> Option<BTreeMap<String, Vec<u8>>> {
+    let data = data.strip_prefix(Secrets::FILE_MAGIC)?;
+    if data.len() < Secrets::NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = data.split_at(Secrets::NONCE_LEN);
+    let plain = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+
+    let mut entries = BTreeMap::new();
+    let mut rest = plain.as_slice();
+    let mut next_field = || {
+        let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let end = 4usize.checked_add(len)?;
+        let field = rest.get(4..end)?.to_vec();
+        rest = &rest[end..];
+        Some(field)
+    };
+    while let Some(name) = next_field() {
+        let value = next_field()?;
+        entries.insert(String::from_utf8(name).ok()?, value);
+    }
+    Some(entries)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn cipher(key: u8) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&[key; 32].into())
+    }
+
+    fn entries() -> BTreeMap<String, Vec<u8>> {
+        BTreeMap::from([
+            ("token".to_string(), b"abc".to_vec()),
+            ("empty".to_string(), Vec::new()),
+            ("binary".to_string(), vec![0, 0xff, 0x80]),
+        ])
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let data = encrypt(&cipher(1), &entries()).unwrap();
+        assert!(data.starts_with(Secrets::FILE_MAGIC));
+        assert_eq!(decrypt(&cipher(1), &data), Some(entries()));
+
+        let empty = encrypt(&cipher(1), &BTreeMap::new()).unwrap();
+        assert_eq!(decrypt(&cipher(1), &empty), Some(BTreeMap::new()));
+    }
+
+    #[test]
+    fn decrypt_with_wrong_key_fails() {
+        let data = encrypt(&cipher(1), &entries()).unwrap();
+        assert_eq!(decrypt(&cipher(2), &data), None);
+    }
+
+    #[test]
+    fn decrypt_truncated_or_tampered_data_fails() {
+        let data = encrypt(&cipher(1), &entries()).unwrap();
+        for len in [
+            0,
+            Secrets::FILE_MAGIC.len() + Secrets::NONCE_LEN - 1,
+            data.len() - 1,
+        ] {
+            assert_eq!(decrypt(&cipher(1), &data[..len]), None, "length {len}");
+        }
+        for i in [0, Secrets::FILE_MAGIC.len(), data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[i] ^= 1;
+            assert_eq!(decrypt(&cipher(1), &tampered), None, "byte {i}");
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(encode_hex(&[0x00, 0x0f, 0xa5, 0xff]), "000fa5ff");
+        assert_eq!(decode_hex("000fa5ff"), Some(vec![0x00, 0x0f, 0xa5, 0xff]));
+        assert_eq!(decode_hex("000FA5FF"), Some(vec![0x00, 0x0f, 0xa5, 0xff]));
+        assert_eq!(decode_hex(""), Some(Vec::new()));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("éa"), None);
+    }
+
+    #[test]
+    fn decrypt_rejects_fields_longer_than_the_data() {
+        for len in [4u32, u32::MAX] {
+            // A complete name, followed by a value shorter than its length.
+            let mut plain = 4u32.to_le_bytes().to_vec();
+            plain.extend_from_slice(b"name");
+            plain.extend_from_slice(&len.to_le_bytes());
+            plain.extend_from_slice(b"abc");
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let mut data = Secrets::FILE_MAGIC.to_vec();
+            data.extend_from_slice(&nonce);
+            data.extend_from_slice(&cipher(1).encrypt(&nonce, plain.as_slice()).unwrap());
+            assert_eq!(decrypt(&cipher(1), &data), None, "length {len}");
+        }
+    }
+
+    #[test]
+    fn open_uses_native_secrets_only_with_both_functions() {
+        let dir = Path::new("/mnt/ext1/system/config/app");
+        assert_eq!(
+            Location::of_app(dir, true, true),
+            Location::Native(dir.join("secrets.cfg"))
+        );
+        for (read, write) in [(true, false), (false, true), (false, false)] {
+            assert_eq!(
+                Location::of_app(dir, read, write),
+                Location::File(dir.join("secrets.bin")),
+                "read {read}, write {write}"
+            );
+        }
+    }
+
+    /// Native secrets kept in memory, counting the saves.
+    #[derive(Default)]
+    struct FakeStore {
+        values: BTreeMap<String, String>,
+        saves: usize,
+    }
+
+    struct FakeNative(Rc<RefCell<FakeStore>>);
+
+    impl NativeSecrets for FakeNative {
+        fn get_secret(&self, key: &str) -> Result<Option<String>, ConfigError> {
+            Ok(self.0.borrow().values.get(key).cloned())
+        }
+
+        fn set_secret(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+            let mut store = self.0.borrow_mut();
+            store.values.insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), ConfigError> {
+            self.0.borrow_mut().values.remove(key);
+            Ok(())
+        }
+
+        fn save(&mut self) -> Result<(), ConfigError> {
+            self.0.borrow_mut().saves += 1;
+            Ok(())
+        }
+    }
+
+    fn native() -> (Secrets, Rc<RefCell<FakeStore>>) {
+        let store = Rc::new(RefCell::new(FakeStore::default()));
+        let secrets = Secrets {
+            backend: Backend::Native(Box::new(FakeNative(store.clone()))),
+        };
+        (secrets, store)
+    }
+
+    #[test]
+    fn native_secrets_are_stored_hex_encoded_and_saved() {
+        let (mut secrets, store) = native();
+        secrets.set("binary", &[0, 0xff, 0x80]).unwrap();
+        secrets.set_string("token", "abc").unwrap();
+
+        assert_eq!(store.borrow().values["binary"], "00ff80");
+        assert_eq!(store.borrow().values["token"], "616263");
+        assert_eq!(store.borrow().saves, 2);
+        assert_eq!(secrets.get("binary").unwrap(), Some(vec![0, 0xff, 0x80]));
+        assert_eq!(
+            secrets.get_string("token").unwrap(),
+            Some("abc".to_string())
+        );
+        assert_eq!(secrets.get_string("binary").unwrap(), None);
+        assert_eq!(secrets.get("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn native_secrets_ignore_values_that_are_not_hex() {
+        let (secrets, store) = native();
+        store
+            .borrow_mut()
+            .values
+            .insert("token".to_string(), "plain".to_string());
+        assert_eq!(secrets.get("token").unwrap(), None);
+    }
+
+    #[test]
+    fn native_remove_saves_only_when_the_secret_existed() {
+        let (mut secrets, store) = native();
+        assert!(!secrets.remove("token").unwrap());
+        assert_eq!(store.borrow().saves, 0);
+
+        secrets.set_string("token", "abc").unwrap();
+        assert!(secrets.remove("token").unwrap());
+        assert!(store.borrow().values.is_empty());
+        assert_eq!(store.borrow().saves, 2);
+    }
+}
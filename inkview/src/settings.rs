@@ -0,0 +1,425 @@
+//! Declarative settings screens on top of the native config editor.
+//!
+//! A [`Settings`] schema is turned into the `iconfigedit` tree expected by `OpenConfigEditor`,
+//! with values stored in an inkview config file.
+
+use crate::bindings;
+use crate::callback::Slot;
+use crate::encoding::{c_string, string_from_ptr, CTree};
+use crate::error::{ConfigError, SettingsError};
+use crate::Error;
+use std::ffi::{c_char, c_int, CString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SettingKind {
+    Text,
+    EnglishText,
+    Password,
+    Number,
+    Url,
+    IpAddress,
+    Checkbox,
+    Choice(Vec<String>),
+    Font,
+    Directory,
+    Info,
+    Submenu(Vec<Setting>),
+}
+
+impl SettingKind {
+    fn raw(&self) -> u32 {
+        match self {
+            Self::Text => bindings::CFG_TEXT,
+            Self::EnglishText => bindings::CFG_ENTEXT,
+            Self::Password => bindings::CFG_PASSWORD,
+            Self::Number => bindings::CFG_NUMBER,
+            Self::Url => bindings::CFG_URL,
+            Self::IpAddress => bindings::CFG_IPADDR,
+            Self::Checkbox => bindings::CFG_CHECKBOX,
+            Self::Choice(_) => bindings::CFG_CHOICE,
+            Self::Font => bindings::CFG_FONT,
+            Self::Directory => bindings::CFG_DIRECTORY,
+            Self::Info => bindings::CFG_INFO,
+            Self::Submenu(_) => bindings::CFG_SUBMENU,
+        }
+    }
+}
+
+/// A single entry of a settings screen.
+///
+/// The value is stored in the config file under `key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    kind: SettingKind,
+    key: String,
+    label: String,
+    hint: Option<String>,
+    default: String,
+    hidden: bool,
+    read_only: bool,
+}
+
+impl Setting {
+    fn new(kind: SettingKind, key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self {
+            kind,
+            key: key.into(),
+            label: label.into(),
+            hint: None,
+            default: String::new(),
+            hidden: false,
+            read_only: false,
+        }
+    }
+
+    /// A free text entry.
+    pub fn text(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Text, key, label)
+    }
+
+    /// A text entry that always starts with the english keyboard layout.
+    pub fn english_text(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::EnglishText, key, label)
+    }
+
+    /// A text entry that hides the entered text.
+    pub fn password(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Password, key, label)
+    }
+
+    pub fn number(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Number, key, label)
+    }
+
+    pub fn url(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Url, key, label)
+    }
+
+    pub fn ip_address(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::IpAddress, key, label)
+    }
+
+    /// An on/off switch, stored as `1` or `0`.
+    pub fn checkbox(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Checkbox, key, label).default("0")
+    }
+
+    /// A choice between `variants`, stored as the chosen variant.
+    pub fn choice<S: Into<String>>(
+        key: impl Into<String>,
+        label: impl Into<String>,
+        variants: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let variants: Vec<String> = variants.into_iter().map(Into::into).collect();
+        let default = variants.first().cloned().unwrap_or_default();
+        Self::new(SettingKind::Choice(variants), key, label).default(default)
+    }
+
+    /// A font selection.
+    pub fn font(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Font, key, label)
+    }
+
+    /// A directory selection.
+    pub fn directory(key: impl Into<String>, label: impl Into<String>) -> Self {
+        Self::new(SettingKind::Directory, key, label)
+    }
+
+    /// A line showing `text`, which can't be edited.
+    pub fn info(label: impl Into<String>, text: impl Into<String>) -> Self {
+        Self::new(SettingKind::Info, "", label).default(text)
+    }
+
+    /// An entry opening a nested settings page.
+    pub fn submenu(label: impl Into<String>, settings: impl IntoIterator<Item = Setting>) -> Self {
+        Self::new(
+            SettingKind::Submenu(settings.into_iter().collect()),
+            "",
+            label,
+        )
+    }
+
+    /// The value used when the config file has no value for the key.
+    pub fn default(mut self, default: impl Into<String>) -> Self {
+        self.default = default.into();
+        self
+    }
+
+    /// A description shown below the label.
+    pub fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Don't show the entry.
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Show the entry, but don't allow editing it.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    fn raw_type(&self) -> c_int {
+        let mut type_ = self.kind.raw();
+        if self.hidden {
+            type_ |= bindings::CFG_HIDDEN;
+        }
+        if self.read_only {
+            type_ |= bindings::CFG_READONLY;
+        }
+        type_ as c_int
+    }
+}
+
+/// A change of a setting made in the editor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingChange {
+    pub key: String,
+    pub value: String,
+}
+
+/// A settings screen, shown through the native config editor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    title: String,
+    path: PathBuf,
+    entries: Vec<Setting>,
+    notify_system: bool,
+}
+
+impl Settings {
+    /// Create a settings screen storing its values in the config file at `path`.
+    pub fn new(title: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            title: title.into(),
+            path: path.into(),
+            entries: Vec::new(),
+            notify_system: false,
+        }
+    }
+
+    /// Create a settings screen storing its values in `settings.cfg` inside
+    /// [`crate::app::config_dir`].
+    pub fn for_app(title: impl Into<String>) -> Self {
+        Self::new(title, crate::app::config_dir().join("settings.cfg"))
+    }
+
+    pub fn setting(mut self, setting: Setting) -> Self {
+        self.entries.push(setting);
+        self
+    }
+
+    /// Call `NotifyConfigChanged` after changes were saved, so other apps receive
+    /// [`crate::Event::ConfigChanged`].
+    ///
+    /// Only needed for config files shared with other apps, like the global config.
+    pub fn notify_system(mut self) -> Self {
+        self.notify_system = true;
+        self
+    }
+
+    /// The config file the values are stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Open the config editor.
+    ///
+    /// `on_change` is called with every changed value, which is already written to the config.
+    /// `on_close` is called after the editor was closed, with the result of saving the config.
+    ///
+    /// Fails with [`SettingsError::AlreadyOpen`] while another editor is open, inkview can't
+    /// close it.
+    pub fn open<C, F>(
+        self,
+        iv: &'static bindings::Inkview,
+        on_change: C,
+        on_close: F,
+    ) -> Result<(), SettingsError>
+    where
+        C: FnMut(SettingChange) + Send + 'static,
+        F: FnOnce(Result<(), ConfigError>) + Send + 'static,
+    {
+        if EDITOR.is_some() {
+            return Err(SettingsError::AlreadyOpen);
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| SettingsError::Io {
+                path: parent.display().to_string(),
+                message: e.to_string(),
+            })?;
+        }
+
+        let path_str = self.path.to_string_lossy().into_owned();
+        let path = c_string(path_str.as_str())?;
+        let title = c_string(self.title)?;
+        let raw = RawSettings::new(&self.entries)?;
+        let root = raw.root;
+        let config = unsafe { iv.OpenConfig(path.as_ptr(), root) };
+        if config.is_null() {
+            return Err(ConfigError::Open { path: path_str }.into());
+        }
+
+        let editor = Editor {
+            iv,
+            _raw: raw,
+            config,
+            changed: false,
+            notify_system: self.notify_system,
+            on_change: Some(Box::new(on_change)),
+            on_close: Box::new(on_close),
+        };
+        if let Some(editor) = EDITOR.restore(editor) {
+            // Another editor was opened meanwhile.
+            unsafe {
+                iv.CloseConfigNoSave(editor.config);
+            }
+            return Err(SettingsError::AlreadyOpen);
+        }
+
+        unsafe {
+            iv.OpenConfigEditor(
+                title.as_ptr(),
+                config,
+                root,
+                Some(editor_close_handler),
+                Some(item_change_handler),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The C representation of the settings, owning all memory the `iconfigedit` arrays point to.
+pub(crate) struct RawSettings {
+    _tree: CTree<bindings::iconfigedit>,
+    pub(crate) root: *mut bindings::iconfigedit,
+}
+
+// The raw pointers only point into heap memory owned by the struct itself.
+unsafe impl Send for RawSettings {}
+
+impl RawSettings {
+    pub(crate) fn new(entries: &[Setting]) -> Result<Self, Error> {
+        let mut tree = CTree::new();
+        let root = Self::build(&mut tree, entries)?;
+        Ok(Self { _tree: tree, root })
+    }
+
+    fn build(
+        tree: &mut CTree<bindings::iconfigedit>,
+        entries: &[Setting],
+    ) -> Result<*mut bindings::iconfigedit, Error> {
+        let mut array = Vec::with_capacity(entries.len() + 1);
+        for setting in entries {
+            let variants = match &setting.kind {
+                SettingKind::Choice(variants) => tree.string_array(variants)?,
+                _ => ptr::null_mut(),
+            };
+            let submenu = match &setting.kind {
+                SettingKind::Submenu(entries) => Self::build(tree, entries)?,
+                _ => ptr::null_mut(),
+            };
+            array.push(bindings::iconfigedit {
+                type_: setting.raw_type(),
+                icon: ptr::null(),
+                text: tree.string(&setting.label)?,
+                hint: match &setting.hint {
+                    Some(hint) => tree.string(hint)?,
+                    None => ptr::null_mut(),
+                },
+                name: tree.optional_string(&setting.key)?,
+                deflt: tree.optional_string(&setting.default)?,
+                variants,
+                submenu,
+                icon_theme: ptr::null_mut(),
+            });
+        }
+        // Terminating entry.
+        array.push(bindings::iconfigedit {
+            type_: 0,
+            icon: ptr::null(),
+            text: ptr::null_mut(),
+            hint: ptr::null_mut(),
+            name: ptr::null_mut(),
+            deflt: ptr::null_mut(),
+            variants: ptr::null_mut(),
+            submenu: ptr::null_mut(),
+            icon_theme: ptr::null_mut(),
+        });
+        Ok(tree.array(array))
+    }
+}
+
+type ChangeCallback = Box<dyn FnMut(SettingChange) + Send>;
+
+struct Editor {
+    iv: &'static bindings::Inkview,
+    _raw: RawSettings,
+    config: *mut bindings::iconfig,
+    changed: bool,
+    notify_system: bool,
+    on_change: Option<ChangeCallback>,
+    on_close: Box<dyn FnOnce(Result<(), ConfigError>) + Send>,
+}
+
+// The config is allocated by inkview and only accessed through the mutex.
+unsafe impl Send for Editor {}
+
+impl Editor {
+    /// Save and close the config, then call the close callback.
+    fn close(self) {
+        let saved = if self.changed {
+            crate::config::save(self.iv, self.config)
+        } else {
+            Ok(())
+        };
+        unsafe {
+            self.iv.CloseConfig(self.config);
+            if saved.is_ok() && self.changed && self.notify_system {
+                self.iv.NotifyConfigChanged();
+            }
+        }
+        (self.on_close)(saved);
+    }
+}
+
+static EDITOR: Slot<Editor> = Slot::new();
+
+extern "C" fn item_change_handler(name: *mut c_char) {
+    if name.is_null() {
+        return;
+    }
+    // Not holding the lock while the callback runs, so it can open other dialogs.
+    let taken = EDITOR.with(|editor| {
+        editor.changed = true;
+        Some((editor.iv, editor.config, editor.on_change.take()?))
+    });
+    let Some((iv, config, mut callback)) = taken.flatten() else {
+        return;
+    };
+
+    let empty = CString::default();
+    let value = unsafe { iv.ReadString(config, name, empty.as_ptr()) };
+    let change = SettingChange {
+        key: unsafe { string_from_ptr(name) }.unwrap_or_default(),
+        value: unsafe { string_from_ptr(value) }.unwrap_or_default(),
+    };
+    callback(change);
+
+    EDITOR.with(|editor| {
+        editor.on_change.get_or_insert(callback);
+    });
+}
+
+extern "C" fn editor_close_handler() {
+    if let Some(editor) = EDITOR.take() {
+        editor.close();
+    }
+}
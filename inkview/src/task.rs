@@ -0,0 +1,437 @@
+//! Tasks of the multi-tasking firmware, and requests between apps.
+//!
+//! Requests carry a payload of bytes to a listener of another task, which replies in place.
+//! The requests of the system are the `REQ_*` constants in [`crate::bindings`].
+
+use crate::bindings;
+use crate::encoding::{c_string, string_from_ptr};
+use crate::error::TaskError;
+use crate::flags::flags;
+use std::ffi::{c_char, c_int, c_void};
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+fn check(code: c_int) -> Result<c_int, TaskError> {
+    if code < 0 {
+        Err(TaskError::Failed(code))
+    } else {
+        Ok(code)
+    }
+}
+
+fn c_len(len: usize) -> Result<c_int, TaskError> {
+    c_int::try_from(len).map_err(|_| TaskError::PayloadTooLarge(len))
+}
+
+flags! {
+    /// Flags of a task, the `TASK_*` constants of inkview.
+    #[derive(Debug)]
+    pub struct TaskFlags;
+
+    /// Not shown in the task list.
+    const HIDDEN = bindings::TASK_HIDDEN;
+    /// Only a single instance of the app may run.
+    const SINGLE_INSTANCE = bindings::TASK_SINGLEINSTANCE;
+    /// Don't kill the task when memory runs low.
+    const NO_FORCED_KILL = bindings::TASK_NOFORCEDKILL;
+    /// Bring the new task to the foreground.
+    const MAKE_ACTIVE = bindings::TASK_MAKEACTIVE;
+    const AUTO_RESTART = bindings::TASK_AUTORESTART;
+}
+
+/// A subtask, like a document opened by a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtask {
+    pub id: i32,
+    pub name: Option<String>,
+    pub book: Option<String>,
+}
+
+/// Information about a running task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub app_name: String,
+    pub flags: TaskFlags,
+    pub pid: i32,
+    pub subtasks: Vec<Subtask>,
+}
+
+/// A running task, identified by its id.
+#[derive(Clone, Copy)]
+pub struct Task {
+    iv: &'static bindings::Inkview,
+    id: c_int,
+}
+
+impl Task {
+    /// The task of this app.
+    pub fn current(iv: &'static bindings::Inkview) -> Self {
+        Self {
+            iv,
+            id: unsafe { iv.GetCurrentTask() },
+        }
+    }
+
+    /// All running tasks.
+    pub fn list(iv: &'static bindings::Inkview) -> Vec<Self> {
+        let mut ids: Vec<c_int> = vec![0; 32];
+        loop {
+            let size = ids.len() as c_int;
+            let count = unsafe { iv.GetTaskList(ids.as_mut_ptr(), size) };
+            if count <= size {
+                ids.truncate(count.max(0) as usize);
+                return ids.into_iter().map(|id| Self { iv, id }).collect();
+            }
+            ids.resize(count as usize, 0);
+        }
+    }
+
+    /// The task of the app with the given name, if it is running.
+    pub fn find(iv: &'static bindings::Inkview, app_name: &str) -> Result<Option<Self>, TaskError> {
+        let app_name = c_string(app_name)?;
+        let id = unsafe { iv.FindTaskByAppName(app_name.as_ptr()) };
+        Ok((id > 0).then_some(Self { iv, id }))
+    }
+
+    /// Start the executable at `path` with `args` in a new task.
+    ///
+    /// `app_name` identifies the app for [`Task::find`], `name` is shown in the task list.
+    pub fn spawn(
+        iv: &'static bindings::Inkview,
+        path: &str,
+        args: &[&str],
+        app_name: &str,
+        name: &str,
+        flags: TaskFlags,
+    ) -> Result<Self, TaskError> {
+        let path = c_string(path)?;
+        let app_name = c_string(app_name)?;
+        let name = c_string(name)?;
+        let args = args
+            .iter()
+            .map(|arg| c_string(*arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut arg_ptrs: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+        arg_ptrs.push(ptr::null());
+        // SDK 5.19 declares the arguments mutable, they are not modified.
+        #[cfg(feature = "sdk-5-19")]
+        let arg_ptrs = arg_ptrs.as_ptr() as *const *mut c_char;
+        #[cfg(not(feature = "sdk-5-19"))]
+        let arg_ptrs = arg_ptrs.as_ptr();
+
+        let id = unsafe {
+            iv.NewTask(
+                path.as_ptr(),
+                arg_ptrs,
+                app_name.as_ptr(),
+                name.as_ptr(),
+                ptr::null(),
+                flags.bits(),
+            )
+        };
+        check(id)?;
+        Ok(Self { iv, id })
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Information about the task, `None` if it is no longer running.
+    pub fn info(&self) -> Option<TaskInfo> {
+        let info = unsafe { self.iv.GetTaskInfo(self.id).as_ref()? };
+        let subtasks = if info.subtasks.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(info.subtasks, info.nsubtasks.max(0) as usize) }
+        };
+        Some(TaskInfo {
+            app_name: unsafe { string_from_ptr(info.appname) }.unwrap_or_default(),
+            flags: TaskFlags(info.flags),
+            pid: info.mainpid,
+            subtasks: subtasks
+                .iter()
+                .map(|subtask| Subtask {
+                    id: subtask.id,
+                    name: unsafe { string_from_ptr(subtask.name) },
+                    book: unsafe { string_from_ptr(subtask.book) },
+                })
+                .collect(),
+        })
+    }
+
+    /// Bring the main subtask of the task to the foreground.
+    pub fn activate(&self) -> Result<(), TaskError> {
+        self.activate_subtask(0)
+    }
+
+    pub fn activate_subtask(&self, subtask: i32) -> Result<(), TaskError> {
+        check(unsafe { self.iv.SetActiveTask(self.id, subtask) }).map(|_| ())
+    }
+
+    /// Ask the task to exit, or kill it if `force` is set.
+    pub fn close(&self, force: bool) -> Result<(), TaskError> {
+        check(unsafe { self.iv.CloseTask(self.id, 0, force as c_int) }).map(|_| ())
+    }
+
+    /// Send a raw event to the event handler of the task.
+    pub fn send_event(&self, event: u32, par1: i32, par2: i32) -> Result<(), TaskError> {
+        check(unsafe { self.iv.SendEventTo(self.id, event as c_int, par1, par2) }).map(|_| ())
+    }
+
+    /// Send `request` with `payload` to the task and wait for a reply of at most
+    /// `reply_capacity` bytes.
+    pub fn request(
+        &self,
+        request: i32,
+        payload: &[u8],
+        reply_capacity: usize,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, TaskError> {
+        send(
+            self.iv,
+            Some(self.id),
+            request,
+            payload,
+            reply_capacity,
+            timeout,
+        )
+    }
+
+    /// Send `request` with `payload` to the task without waiting for a reply.
+    pub fn notify(&self, request: i32, payload: &[u8]) -> Result<(), TaskError> {
+        let mut data = payload.to_vec();
+        let code = unsafe {
+            self.iv.SendRequestToNoWait(
+                self.id,
+                request,
+                data.as_mut_ptr() as *mut c_void,
+                c_len(data.len())?,
+                0,
+            )
+        };
+        check(code).map(|_| ())
+    }
+
+    /// Send `request` with a JSON encoded payload and decode the reply.
+    ///
+    /// Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn request_json<T, R>(
+        &self,
+        request: i32,
+        payload: &T,
+        reply_capacity: usize,
+        timeout: Duration,
+    ) -> Result<R, TaskError>
+    where
+        T: serde::Serialize,
+        R: serde::de::DeserializeOwned,
+    {
+        let payload = serde_json::to_vec(payload).map_err(|e| TaskError::Payload(e.to_string()))?;
+        let reply = self.request(request, &payload, reply_capacity, timeout)?;
+        serde_json::from_slice(&reply).map_err(|e| TaskError::Payload(e.to_string()))
+    }
+}
+
+impl PartialEq for Task {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Task {}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Task").field(&self.id).finish()
+    }
+}
+
+/// Send `request` with `payload` to the system, e.g. one of the `REQ_*` requests, and wait
+/// for a reply of at most `reply_capacity` bytes.
+pub fn request(
+    iv: &bindings::Inkview,
+    request: i32,
+    payload: &[u8],
+    reply_capacity: usize,
+    timeout: Duration,
+) -> Result<Vec<u8>, TaskError> {
+    send(iv, None, request, payload, reply_capacity, timeout)
+}
+
+fn send(
+    iv: &bindings::Inkview,
+    task: Option<c_int>,
+    request: i32,
+    payload: &[u8],
+    reply_capacity: usize,
+    timeout: Duration,
+) -> Result<Vec<u8>, TaskError> {
+    // The reply is written over the payload.
+    let mut data = vec![0u8; payload.len().max(reply_capacity)];
+    data[..payload.len()].copy_from_slice(payload);
+    let inlen = c_len(payload.len())?;
+    let outlen = c_len(reply_capacity)?;
+    let timeout = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+    let data_ptr = data.as_mut_ptr() as *mut c_void;
+    let len = check(unsafe {
+        match task {
+            Some(task) => iv.SendRequestTo(task, request, data_ptr, inlen, outlen, timeout),
+            None => iv.SendRequest(request, data_ptr, inlen, outlen, timeout),
+        }
+    })?;
+    data.truncate((len as usize).min(reply_capacity));
+    Ok(data)
+}
+
+/// Send the global request `request` to all tasks.
+pub fn send_global_request(iv: &bindings::Inkview, request: i32) -> Result<(), TaskError> {
+    check(unsafe { iv.SendGlobalRequest(request) }).map(|_| ())
+}
+
+/// Send this app to the background, showing the previous task.
+pub fn go_to_background(iv: &bindings::Inkview) {
+    unsafe {
+        iv.GoToBackground();
+    }
+}
+
+type Handler = Box<dyn FnMut(&[u8]) -> Vec<u8> + Send>;
+
+/// The registered listeners, by request and registration. A handler is taken out while it
+/// runs.
+static LISTENERS: Mutex<Vec<(c_int, u64, Option<Handler>)>> = Mutex::new(Vec::new());
+
+/// Handles requests of other tasks, unregistered on drop.
+#[must_use = "the listener is unregistered when dropped"]
+pub struct RequestListener {
+    iv: &'static bindings::Inkview,
+    request: c_int,
+    registration: u64,
+}
+
+impl RequestListener {
+    /// Reply to `request` with the return value of `handler`, which receives the payload.
+    ///
+    /// Replies are truncated to the capacity the sender asked for. Replaces an existing
+    /// listener for `request`.
+    pub fn new<F>(
+        iv: &'static bindings::Inkview,
+        request: i32,
+        handler: F,
+    ) -> Result<Self, TaskError>
+    where
+        F: FnMut(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        let registration = NEXT.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut listeners = LISTENERS.lock().unwrap();
+            listeners.retain(|(r, _, _)| *r != request);
+            listeners.push((request, registration, Some(Box::new(handler))));
+        }
+        let code = unsafe {
+            iv.SetRequestListener(
+                request,
+                bindings::RQL_REPLACE as c_int,
+                Some(handle_request),
+            )
+        };
+        if let Err(e) = check(code) {
+            remove_listener(request, registration);
+            return Err(e);
+        }
+        Ok(Self {
+            iv,
+            request,
+            registration,
+        })
+    }
+
+    /// Reply to `request` with the JSON encoded return value of `handler`, which receives
+    /// the decoded payload. Payloads that fail to decode get an empty reply.
+    ///
+    /// Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn json<T, R, F>(
+        iv: &'static bindings::Inkview,
+        request: i32,
+        mut handler: F,
+    ) -> Result<Self, TaskError>
+    where
+        T: serde::de::DeserializeOwned,
+        R: serde::Serialize,
+        F: FnMut(T) -> R + Send + 'static,
+    {
+        Self::new(iv, request, move |payload| {
+            serde_json::from_slice(payload)
+                .ok()
+                .and_then(|payload| serde_json::to_vec(&handler(payload)).ok())
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for RequestListener {
+    fn drop(&mut self) {
+        if remove_listener(self.request, self.registration) {
+            unsafe {
+                self.iv.SetRequestListener(
+                    self.request,
+                    bindings::RQL_REMOVE as c_int,
+                    Some(handle_request),
+                );
+            }
+        }
+    }
+}
+
+/// Remove a registration, returns whether it was still registered.
+fn remove_listener(request: c_int, registration: u64) -> bool {
+    let mut listeners = LISTENERS.lock().unwrap();
+    let len = listeners.len();
+    listeners.retain(|(r, reg, _)| (*r, *reg) != (request, registration));
+    listeners.len() != len
+}
+
+unsafe extern "C" fn handle_request(
+    request: c_int,
+    data: *mut c_void,
+    inlen: c_int,
+    outlen: c_int,
+) -> c_int {
+    let taken = LISTENERS
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|(r, _, _)| *r == request)
+        .and_then(|(_, registration, handler)| Some((*registration, handler.take()?)));
+    let Some((registration, mut handler)) = taken else {
+        return 0;
+    };
+
+    let reply = if data.is_null() {
+        handler(&[])
+    } else {
+        handler(unsafe { std::slice::from_raw_parts(data as *const u8, inlen.max(0) as usize) })
+    };
+    let len = reply.len().min(outlen.max(0) as usize);
+    if !data.is_null() {
+        unsafe { ptr::copy_nonoverlapping(reply.as_ptr(), data as *mut u8, len) };
+    }
+
+    // Put the handler back, unless the listener was dropped or replaced meanwhile.
+    if let Some((_, _, slot)) = LISTENERS
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|(r, reg, _)| (*r, *reg) == (request, registration))
+    {
+        *slot = Some(handler);
+    }
+    len as c_int
+}
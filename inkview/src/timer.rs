@@ -0,0 +1,353 @@
+//! Timers running Rust closures.
+//!
+//! Timer callbacks are invoked by inkview on the main thread in between events, so they may
+//! call into inkview directly, e.g. to redraw a clock.
+
+use crate::bindings;
+use std::collections::BTreeMap;
+use std::ffi::{c_void, CStr, CString};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+type TimerCallback = Box<dyn FnMut() + Send>;
+
+/// The native timer functions, behind a trait so the bookkeeping can be tested without a device.
+trait Backend: Sync {
+    fn arm(&self, name: &CStr, id: usize, ms: i32, weak: bool);
+    fn clear(&self, name: &CStr);
+    fn is_pending(&self, id: usize) -> bool;
+}
+
+impl Backend for bindings::Inkview {
+    fn arm(&self, name: &CStr, id: usize, ms: i32, weak: bool) {
+        unsafe {
+            if weak {
+                self.SetWeakTimerEx(name.as_ptr(), Some(timer_trampoline), id as *mut c_void, ms);
+            } else {
+                self.SetHardTimerEx(name.as_ptr(), Some(timer_trampoline), id as *mut c_void, ms);
+            }
+        }
+    }
+
+    fn clear(&self, name: &CStr) {
+        unsafe {
+            self.ClearTimerByName(name.as_ptr());
+        }
+    }
+
+    fn is_pending(&self, id: usize) -> bool {
+        unsafe { self.QueryTimerEx(Some(timer_trampoline), id as *mut c_void) != 0 }
+    }
+}
+
+struct Entry {
+    backend: &'static dyn Backend,
+    name: CString,
+    interval: Duration,
+    repeating: bool,
+    weak: bool,
+    /// Taken out while the callback runs.
+    callback: Option<TimerCallback>,
+    /// The handle was detached, the entry is removed after a one-shot timer fired.
+    detached: bool,
+}
+
+impl Entry {
+    fn arm(&self, id: usize) {
+        let ms = self.interval.as_millis().min(i32::MAX as u128) as i32;
+        self.backend.arm(&self.name, id, ms, self.weak);
+    }
+}
+
+static TIMERS: Mutex<BTreeMap<usize, Entry>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A scheduled timer.
+///
+/// Dropping the handle cancels the timer, use [`Timer::detach`] to keep it running.
+#[derive(Debug)]
+#[must_use = "dropping a timer cancels it"]
+pub struct Timer {
+    id: usize,
+}
+
+impl Timer {
+    /// Run `callback` once after `delay`.
+    ///
+    /// Hard timers wake up the device from sleep.
+    pub fn once<F: FnOnce() + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        delay: Duration,
+        callback: F,
+    ) -> Self {
+        Self::start(iv, delay, false, false, once_callback(callback))
+    }
+
+    /// Run `callback` every `interval`.
+    ///
+    /// Hard timers wake up the device from sleep.
+    pub fn repeating<F: FnMut() + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        interval: Duration,
+        callback: F,
+    ) -> Self {
+        Self::start(iv, interval, true, false, Box::new(callback))
+    }
+
+    /// Run `callback` once after `delay`, without waking up the device from sleep.
+    ///
+    /// If the device sleeps when the timer expires, it fires after wake-up.
+    pub fn once_weak<F: FnOnce() + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        delay: Duration,
+        callback: F,
+    ) -> Self {
+        Self::start(iv, delay, false, true, once_callback(callback))
+    }
+
+    /// Run `callback` every `interval`, without waking up the device from sleep.
+    pub fn repeating_weak<F: FnMut() + Send + 'static>(
+        iv: &'static bindings::Inkview,
+        interval: Duration,
+        callback: F,
+    ) -> Self {
+        Self::start(iv, interval, true, true, Box::new(callback))
+    }
+
+    fn start(
+        backend: &'static dyn Backend,
+        interval: Duration,
+        repeating: bool,
+        weak: bool,
+        callback: TimerCallback,
+    ) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let entry = Entry {
+            backend,
+            name: CString::new(format!("inkview-rs-timer-{id}")).unwrap(),
+            interval,
+            repeating,
+            weak,
+            callback: Some(callback),
+            detached: false,
+        };
+        entry.arm(id);
+        TIMERS.lock().unwrap().insert(id, entry);
+
+        Self { id }
+    }
+
+    /// Whether the timer is still scheduled to fire.
+    pub fn is_pending(&self) -> bool {
+        let timers = TIMERS.lock().unwrap();
+        let Some(entry) = timers.get(&self.id) else {
+            return false;
+        };
+        entry.backend.is_pending(self.id)
+    }
+
+    /// Keep the timer running without holding on to the handle.
+    ///
+    /// A detached repeating timer runs until the app exits.
+    pub fn detach(self) {
+        if let Some(entry) = TIMERS.lock().unwrap().get_mut(&self.id) {
+            entry.detached = true;
+        }
+        std::mem::forget(self);
+    }
+
+    /// Cancel the timer.
+    ///
+    /// Equivalent to dropping the handle.
+    pub fn cancel(self) {}
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let entry = TIMERS.lock().unwrap().remove(&self.id);
+        if let Some(entry) = entry {
+            entry.backend.clear(&entry.name);
+        }
+    }
+}
+
+fn once_callback<F: FnOnce() + Send + 'static>(callback: F) -> TimerCallback {
+    let mut callback = Some(callback);
+    Box::new(move || {
+        if let Some(callback) = callback.take() {
+            callback()
+        }
+    })
+}
+
+extern "C" fn timer_trampoline(context: *mut c_void) {
+    let id = context as usize;
+
+    // The lock is not held while the callback runs, so it can create and cancel timers,
+    // including its own.
+    let Some(mut callback) = TIMERS
+        .lock()
+        .unwrap()
+        .get_mut(&id)
+        .and_then(|e| e.callback.take())
+    else {
+        return;
+    };
+
+    callback();
+
+    let mut timers = TIMERS.lock().unwrap();
+    let Some(entry) = timers.get_mut(&id) else {
+        // Cancelled from within the callback.
+        return;
+    };
+    if entry.repeating {
+        entry.callback = Some(callback);
+        entry.arm(id);
+    } else if entry.detached {
+        timers.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Records the armed and cleared timers instead of scheduling them.
+    #[derive(Default)]
+    struct FakeBackend {
+        armed: Mutex<Vec<(usize, i32, bool)>>,
+        cleared: Mutex<Vec<CString>>,
+    }
+
+    impl Backend for FakeBackend {
+        fn arm(&self, _name: &CStr, id: usize, ms: i32, weak: bool) {
+            self.armed.lock().unwrap().push((id, ms, weak));
+        }
+
+        fn clear(&self, name: &CStr) {
+            self.cleared.lock().unwrap().push(name.to_owned());
+        }
+
+        fn is_pending(&self, id: usize) -> bool {
+            self.armed
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(armed, ..)| *armed == id)
+        }
+    }
+
+    fn backend() -> &'static FakeBackend {
+        Box::leak(Box::default())
+    }
+
+    fn fire(timer_id: usize) {
+        timer_trampoline(timer_id as *mut c_void);
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, impl FnMut() + Send + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let callback = {
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        (count, callback)
+    }
+
+    #[test]
+    fn once_fires_once_and_is_cleared_on_drop() {
+        let backend = backend();
+        let (count, callback) = counter();
+        let timer = Timer::start(
+            backend,
+            Duration::from_millis(1500),
+            false,
+            true,
+            once_callback(callback),
+        );
+        let id = timer.id;
+        assert_eq!(*backend.armed.lock().unwrap(), [(id, 1500, true)]);
+        assert!(timer.is_pending());
+
+        fire(id);
+        fire(id);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // Not re-armed.
+        assert_eq!(backend.armed.lock().unwrap().len(), 1);
+
+        drop(timer);
+        assert!(!TIMERS.lock().unwrap().contains_key(&id));
+        assert_eq!(backend.cleared.lock().unwrap().len(), 1);
+        fire(id);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn repeating_is_rearmed_until_cancelled() {
+        let backend = backend();
+        let (count, callback) = counter();
+        let timer = Timer::start(
+            backend,
+            Duration::from_secs(1),
+            true,
+            false,
+            Box::new(callback),
+        );
+        let id = timer.id;
+
+        fire(id);
+        fire(id);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert_eq!(backend.armed.lock().unwrap().len(), 3);
+
+        timer.cancel();
+        fire(id);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert_eq!(backend.cleared.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn detached_once_is_removed_after_firing() {
+        let backend = backend();
+        let (count, callback) = counter();
+        let timer = Timer::start(
+            backend,
+            Duration::ZERO,
+            false,
+            false,
+            once_callback(callback),
+        );
+        let id = timer.id;
+        timer.detach();
+        assert!(TIMERS.lock().unwrap().contains_key(&id));
+
+        fire(id);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(!TIMERS.lock().unwrap().contains_key(&id));
+        // Detached timers are never cleared, they expired.
+        assert!(backend.cleared.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn callback_may_cancel_its_own_timer() {
+        let backend = backend();
+        let slot: Arc<Mutex<Option<Timer>>> = Arc::default();
+        let timer = Timer::start(backend, Duration::from_secs(1), true, false, {
+            let slot = slot.clone();
+            Box::new(move || drop(slot.lock().unwrap().take()))
+        });
+        let id = timer.id;
+        *slot.lock().unwrap() = Some(timer);
+
+        fire(id);
+        assert!(!TIMERS.lock().unwrap().contains_key(&id));
+        // Cancelled instead of re-armed.
+        assert_eq!(backend.armed.lock().unwrap().len(), 1);
+        assert_eq!(backend.cleared.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,283 @@
+//! Wi-Fi scanning and saved networks.
+//!
+//! Lists returned by the firmware are copied and left to the firmware, which owns them.
+
+use crate::bindings;
+use crate::config::Config;
+use crate::encoding::{c_string, string_from_array, string_from_ptr};
+use crate::error::{NetError, WifiError};
+use crate::net::check;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Security of a Wi-Fi network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    Open,
+    Wep,
+    WpaEap,
+    WpaPsk,
+    Other(u32),
+}
+
+impl Security {
+    fn from_raw(raw: bindings::WIFI_SECURITY) -> Self {
+        match raw {
+            bindings::WIFI_SECURITY_NO => Self::Open,
+            bindings::WIFI_SECURITY_WEP => Self::Wep,
+            bindings::WIFI_SECURITY_WPAEAP => Self::WpaEap,
+            bindings::WIFI_SECURITY_WPAPSK => Self::WpaPsk,
+            raw => Self::Other(raw),
+        }
+    }
+}
+
+/// A Wi-Fi access point found by a scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPoint {
+    pub ssid: String,
+    pub mode: i32,
+    pub channel: i32,
+    pub security: Security,
+    /// Link quality, as reported by the driver.
+    pub quality: i32,
+    /// Signal level, as reported by the driver.
+    pub level: i32,
+    pub noise: i32,
+    pub mac: [u8; 6],
+}
+
+impl AccessPoint {
+    fn from_raw(ap: &bindings::wifiapinfo) -> Self {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&ap.mac[..6]);
+        Self {
+            ssid: string_from_array(&ap.ssid),
+            mode: ap.mode,
+            channel: ap.channel,
+            security: Security::from_raw(ap.security),
+            quality: ap.quality,
+            level: ap.level,
+            noise: ap.noise,
+            mac,
+        }
+    }
+}
+
+/// Copy the access points out of a list returned by inkview.
+///
+/// # Safety
+///
+/// `list` must be null or point to a valid access point list.
+unsafe fn access_points(list: *const bindings::iv_wifi_ap_list) -> Vec<AccessPoint> {
+    let Some(list) = (unsafe { list.as_ref() }) else {
+        return Vec::new();
+    };
+    let count = list.ap_quantity.max(0) as usize;
+    unsafe { list.apinfo.as_slice(count) }
+        .iter()
+        .map(AccessPoint::from_raw)
+        .collect()
+}
+
+/// The access points found by the last scan of the firmware.
+pub fn scan_results(iv: &bindings::Inkview) -> Vec<AccessPoint> {
+    unsafe { access_points(iv.GetWiFiScanResults(std::ptr::null_mut())) }
+}
+
+/// A running background Wi-Fi scan, stopped on drop.
+#[must_use = "the scan is stopped when dropped"]
+pub struct Scan {
+    iv: &'static bindings::Inkview,
+}
+
+impl Scan {
+    /// Start scanning for Wi-Fi networks in the background.
+    pub fn start(iv: &'static bindings::Inkview) -> Result<Self, NetError> {
+        check(unsafe { iv.WiFiScanProcessStart() })?;
+        Ok(Self { iv })
+    }
+
+    /// The access points found so far.
+    pub fn results(&self) -> Vec<AccessPoint> {
+        unsafe { access_points(self.iv.WiFiScanProcessGetResults()) }
+    }
+}
+
+impl Drop for Scan {
+    fn drop(&mut self) {
+        unsafe {
+            self.iv.WiFiScanProcessStop();
+        }
+    }
+}
+
+/// Names of the saved network connections, through `EnumConnections`.
+pub fn saved_networks(iv: &bindings::Inkview) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut entry = unsafe { iv.EnumConnections() };
+    if entry.is_null() {
+        return names;
+    }
+    while let Some(name) = unsafe { string_from_ptr(*entry) } {
+        names.push(name);
+        entry = unsafe { entry.add(1) };
+    }
+    names
+}
+
+/// Save a Wi-Fi network, so the firmware can connect to it.
+///
+/// `key` is the WEP key or WPA passphrase, it is ignored for open networks. Networks using
+/// [`Security::WpaEap`] need more than a key and are not supported, use
+/// [`add_network_from_file`] for them.
+///
+/// The network is written to a temporary network config file with the keys `name`, `type`,
+/// `ssid`, `security` (`none`, `wep` or `wpa-psk`) and `key`, which is passed to `NetAdd`. The
+/// file is only readable by the app and removed right after.
+///
+/// The SDK does not document this format and it is unverified against the firmware. If adding
+/// fails, compare with a network saved in the settings, exported with [`export_networks`], and
+/// use [`add_network_from_file`].
+pub fn add_network(
+    iv: &'static bindings::Inkview,
+    ssid: &str,
+    security: Security,
+    key: &str,
+) -> Result<(), WifiError> {
+    let security = match security {
+        Security::Open => "none",
+        Security::Wep => "wep",
+        Security::WpaPsk => "wpa-psk",
+        Security::WpaEap | Security::Other(_) => return Err(WifiError::UnsupportedSecurity),
+    };
+    let dir = PrivateDir::create()?;
+    let path = dir.0.join("network.cfg");
+    write_network_config(iv, &path, ssid, security, key)?;
+    add_network_from_file(iv, &path)
+}
+
+/// A temporary directory only accessible by the app, removed with its contents on drop.
+struct PrivateDir(PathBuf);
+
+impl PrivateDir {
+    fn create() -> Result<Self, WifiError> {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let mut builder = std::fs::DirBuilder::new();
+        builder.mode(0o700);
+        loop {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos();
+            let path = std::env::temp_dir().join(format!(
+                "inkview-rs-{}-{}-{nanos:08x}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+            ));
+            // Fails instead of reusing a directory that someone else created.
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(io_error(&path, e)),
+            }
+        }
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> WifiError {
+    WifiError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    }
+}
+
+fn write_network_config(
+    iv: &'static bindings::Inkview,
+    path: &Path,
+    ssid: &str,
+    security: &str,
+    key: &str,
+) -> Result<(), WifiError> {
+    // Created before inkview writes to it, so the key is never readable by others.
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| io_error(path, e))?;
+    let mut config = Config::open(iv, path)?;
+    config.set_string("name", ssid)?;
+    config.set_string("type", "wifi")?;
+    config.set_string("ssid", ssid)?;
+    config.set_string("security", security)?;
+    if security != "none" {
+        config.set_string("key", key)?;
+    }
+    Ok(config.save()?)
+}
+
+/// Add the network described by the network config file at `path`, through `NetAdd`.
+///
+/// The file is an inkview config file, see [`add_network`] for the keys of a Wi-Fi network.
+pub fn add_network_from_file(
+    iv: &bindings::Inkview,
+    path: impl AsRef<Path>,
+) -> Result<(), WifiError> {
+    let path = c_string(path.as_ref().to_string_lossy().into_owned())?;
+    Ok(check(unsafe { iv.NetAdd(path.as_ptr()) })?)
+}
+
+/// Write the list of saved networks to the file at `path`, through `GetNetList`.
+///
+/// The file is an inkview config file, which can be read with [`Config`].
+pub fn export_networks(iv: &bindings::Inkview, path: impl AsRef<Path>) -> Result<(), WifiError> {
+    let path = c_string(path.as_ref().to_string_lossy().into_owned())?;
+    Ok(check(unsafe { iv.GetNetList(path.as_ptr()) })?)
+}
+
+/// Delete the saved network with the given SSID.
+pub fn delete_network(iv: &bindings::Inkview, ssid: &str) -> Result<(), WifiError> {
+    let ssid = c_string(ssid)?;
+    Ok(check(unsafe { iv.NetDelete_by_ssid(ssid.as_ptr()) })?)
+}
+
+/// Make the saved network with the given SSID the one to connect to.
+pub fn select_network(iv: &bindings::Inkview, ssid: &str) -> Result<(), WifiError> {
+    let ssid = c_string(ssid)?;
+    Ok(check(unsafe { iv.NetSelect_by_ssid(ssid.as_ptr()) })?)
+}
+
+/// Signal quality of the current connection, `None` if not connected.
+pub fn signal_quality(iv: &bindings::Inkview) -> Option<i32> {
+    let quality = unsafe { iv.GetNetSignalQuality() };
+    (quality >= 0).then_some(quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn private_dirs_are_unique_and_removed_on_drop() {
+        let first = PrivateDir::create().unwrap();
+        let second = PrivateDir::create().unwrap();
+        assert_ne!(first.0, second.0);
+
+        let mode = std::fs::metadata(&first.0).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let path = first.0.clone();
+        std::fs::write(path.join("network.cfg"), "key=secret").unwrap();
+        drop(first);
+        assert!(!path.exists());
+    }
+}